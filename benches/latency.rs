@@ -0,0 +1,85 @@
+//! Measures how long the hook callback takes per key press, while the timer thread fires
+//! timeouts in the background, and counts the allocations made on the callback's thread.
+//!
+//! ```bash
+//! cargo bench --bench latency
+//! ```
+
+mod common;
+
+use common::allocations;
+use keyboard_hook::engine::Engine;
+use keyboard_hook::keymap;
+use keyboard_hook::runtime::Runtime;
+use keyboard_hook::types::Key::*;
+use keyboard_hook::types::KeyPress;
+use keyboard_hook::types::Modifier::*;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ROUNDS: usize = 20_000;
+
+fn main() {
+    let (engine, _) = Engine::new(keymap! {
+        "<A-a> w" => "Kenny",
+        "<A-a> q" => "Princess" on timeout,
+        "<A-a> s 2" => "UseStrip2" on timeout,
+        "<A-a> [jk]*" as "Volume" => { j => "VolumeDown", k => "VolumeUp" },
+    });
+    // A short timeout keeps the timer thread busy, so the callback competes with it for the lock.
+    let engine = engine.with_timeout(Duration::from_micros(50));
+    let (tx, rx) = mpsc::channel();
+    let consumer = thread::spawn(move || rx.into_iter().count());
+    let runtime = Runtime::new(engine, tx);
+
+    let alt_a = KeyPress::Mod(KeyA, ModAlt);
+    let sequences: [&[KeyPress]; 4] = [
+        &[alt_a.clone(), KeyW.into()],
+        &[alt_a.clone(), KeyQ.into()],
+        &[alt_a.clone(), KeyS.into(), Key2.into()],
+        &[alt_a, KeyJ.into(), KeyJ.into(), KeyK.into()],
+    ];
+    let unmapped = [KeyX.into(), KeyY.into()];
+
+    let keys_per_round: usize = sequences.iter().map(|s| s.len()).sum::<usize>() + unmapped.len();
+    let mut latencies = Vec::with_capacity(ROUNDS * keys_per_round);
+    let mut allocated = 0;
+
+    for round in 0..ROUNDS {
+        let sequence = sequences[round % sequences.len()];
+
+        for key in sequence.iter().chain(unmapped.iter()) {
+            let before = allocations();
+            let start = Instant::now();
+            let _ = runtime.handle(key);
+            let elapsed = start.elapsed();
+            allocated += allocations() - before;
+            latencies.push(elapsed);
+        }
+
+        // Every other round, let the timeout run out.
+        if round % 2 == 0 {
+            thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    let over_budget = runtime.latency().over_budget();
+    drop(runtime);
+    let events = consumer.join().unwrap();
+
+    latencies.sort();
+    let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+
+    println!("key presses:  {}", latencies.len());
+    println!("events:       {}", events);
+    println!("p50:          {:?}", percentile(0.5));
+    println!("p99:          {:?}", percentile(0.99));
+    println!("p99.9:        {:?}", percentile(0.999));
+    println!("worst case:   {:?}", latencies[latencies.len() - 1]);
+    println!("over budget:  {}", over_budget);
+    println!(
+        "allocations:  {:.3} per key press",
+        allocated as f64 / latencies.len() as f64
+    );
+}
@@ -0,0 +1,174 @@
+//! Per-key cost of looking key presses up in the trie and of handling them in the engine, with
+//! large synthetic keymaps, followed by the heap allocations each key press makes.
+//!
+//! ```bash
+//! cargo bench --bench trie
+//! ```
+
+mod common;
+
+use common::allocations;
+use criterion::{criterion_group, BenchmarkId, Criterion, Throughput};
+use keyboard_hook::engine::Engine;
+use keyboard_hook::mapping_trie::Cursor;
+use keyboard_hook::mapping_trie::MappingLookup;
+use keyboard_hook::mapping_trie::MappingTrie;
+use keyboard_hook::types::Behaviour;
+use keyboard_hook::types::Behaviours;
+use keyboard_hook::types::Key;
+use keyboard_hook::types::KeyPress;
+use keyboard_hook::types::Mapping;
+use keyboard_hook::types::Modifier::*;
+use std::hint::black_box;
+use std::time::Instant;
+
+type Sequence = Vec<Mapping<u32, u32>>;
+
+const SEQUENCES: u32 = 5_000;
+const DEPTHS: [usize; 4] = [1, 10, 100, 1_000];
+
+fn letter(i: u32) -> Key {
+    Key::from_u8(b'A' + (i % 26) as u8)
+}
+
+fn digit(i: u32) -> Key {
+    Key::from_u8(b'0' + (i % 6) as u8)
+}
+
+/// Thousands of sequences like `<A-b> c d e`, all ending with an action, plus `<A-z> [0-5]*`.
+fn keymap() -> Vec<Sequence> {
+    let mut sequences: Vec<Sequence> = (0..SEQUENCES)
+        .map(|i| {
+            let keys = [
+                KeyPress::Mod(letter(i), ModAlt),
+                letter(i / 26).into(),
+                letter(i / 26 / 26).into(),
+            ];
+            let last = letter(i / 26 / 26 / 26).into();
+            let behaviour = if i % 2 == 0 {
+                Behaviour::Action(last, i)
+            } else {
+                Behaviour::ActionOnTimeout(last, i)
+            };
+
+            keys.into_iter()
+                .map(|key| Mapping::Single(Behaviour::Timeout(key)))
+                .chain([Mapping::Single(behaviour)])
+                .collect()
+        })
+        .collect();
+
+    let choice = Behaviours(
+        (0..6)
+            .map(|i| Behaviour::ActionOnTimeout(digit(i).into(), i))
+            .collect(),
+    );
+    sequences.push(vec![
+        Mapping::Single(Behaviour::Timeout(KeyPress::Mod(Key::KeyZ, ModAlt))),
+        Mapping::Choice(choice, 0),
+    ]);
+
+    sequences
+}
+
+/// `<A-z>` followed by `depth` keys of the repeatable choice.
+fn choice_run(depth: usize) -> Vec<KeyPress> {
+    [KeyPress::Mod(Key::KeyZ, ModAlt)]
+        .into_iter()
+        .chain((0..depth as u32).map(|i| digit(i).into()))
+        .collect()
+}
+
+fn lookup(c: &mut Criterion) {
+    let (trie, conflicts) = MappingTrie::new(&keymap().into());
+    assert!(conflicts.is_empty());
+
+    let mut group = c.benchmark_group("lookup");
+    let sequence = [
+        KeyPress::Mod(Key::KeyB, ModAlt),
+        Key::KeyC.into(),
+        Key::KeyD.into(),
+    ];
+    group.bench_function("sequence", |b| {
+        b.iter(|| trie.find_mapping(black_box(&Key::KeyA.into()), black_box(&sequence)))
+    });
+
+    for depth in DEPTHS {
+        let buffer = choice_run(depth);
+        group.bench_with_input(BenchmarkId::new("choice", depth), &buffer, |b, buffer| {
+            b.iter(|| trie.find_mapping(black_box(&Key::Key1.into()), black_box(buffer)))
+        });
+    }
+
+    // What the engine does per key press: one step from where the previous key left the cursor.
+    let cursor = choice_run(DEPTHS[3])
+        .iter()
+        .try_fold(Cursor::root(), |cursor, key| {
+            trie.advance(cursor, key).map(|(cursor, _, _)| cursor)
+        })
+        .unwrap();
+    group.bench_function("advance", |b| {
+        b.iter(|| trie.advance(black_box(cursor), black_box(&Key::Key1.into())))
+    });
+
+    group.finish();
+}
+
+fn keystroke(c: &mut Criterion) {
+    let mut group = c.benchmark_group("keystroke");
+
+    for depth in DEPTHS {
+        let (mut engine, _) = Engine::new(keymap());
+        let keys = choice_run(depth);
+        let unmapped: KeyPress = Key::KeyX.into();
+
+        // Feeds the whole run and ends it with an unmapped key, so each iteration starts afresh.
+        group.throughput(Throughput::Elements(keys.len() as u64 + 1));
+        group.bench_with_input(BenchmarkId::new("choice", depth), &keys, |b, keys| {
+            b.iter(|| {
+                let now = Instant::now();
+
+                for key in keys {
+                    black_box(engine.feed(key, now));
+                }
+
+                black_box(engine.feed(&unmapped, now));
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Allocations per key press, counted once per depth since they don't vary between runs.
+fn report_allocations() {
+    let (trie, _) = MappingTrie::new(&keymap().into());
+
+    println!("\nAllocations per key press:");
+
+    for depth in DEPTHS {
+        let (mut engine, _) = Engine::new(keymap());
+        let keys = choice_run(depth);
+        let now = Instant::now();
+
+        let before = allocations();
+        black_box(trie.find_mapping(&Key::Key1.into(), &keys));
+        let lookup = allocations() - before;
+
+        let before = allocations();
+        for key in &keys {
+            black_box(engine.feed(key, now));
+        }
+        let keystroke = (allocations() - before) as f64 / keys.len() as f64;
+
+        println!("  depth {depth:>4}: lookup {lookup}, keystroke {keystroke:.2}");
+    }
+}
+
+criterion_group!(benches, lookup, keystroke);
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    report_allocations();
+}
@@ -1,15 +1,13 @@
 use core::fmt;
-use keyboard_hook::a;
-use keyboard_hook::aot;
-use keyboard_hook::c;
-use keyboard_hook::key_a;
-use keyboard_hook::key_aot;
-use keyboard_hook::shutdown;
-use keyboard_hook::t;
+use keyboard_hook::alt;
+use keyboard_hook::cheat_sheet::CheatSheet;
+use keyboard_hook::fragment::Fragment;
+use keyboard_hook::fragment::Mappings;
+use keyboard_hook::key;
+use keyboard_hook::keymap;
 use keyboard_hook::types::Event;
 use keyboard_hook::types::Key::*;
 use keyboard_hook::types::Mapping;
-use keyboard_hook::types::Modifier::*;
 use keyboard_hook::types::SystemAction;
 use keyboard_hook::ActionHandler;
 use keyboard_hook::KeyboardHook;
@@ -25,11 +23,7 @@ enum MyActions {
     Kenny,
     VolumeUp,
     VolumeDown,
-    ToggleChannel1,
-    ToggleChannel2,
-    ToggleChannel3,
-    ToggleChannel4,
-    ToggleChannel5,
+    ToggleChannel(u8),
     UseStrip2,
 }
 
@@ -42,11 +36,7 @@ impl Display for MyActions {
             MyActions::Kenny => write!(f, "Kenny"),
             MyActions::VolumeUp => write!(f, "VolumeUp"),
             MyActions::VolumeDown => write!(f, "VolumeDown"),
-            MyActions::ToggleChannel1 => write!(f, "ToggleChannel1"),
-            MyActions::ToggleChannel2 => write!(f, "ToggleChannel2"),
-            MyActions::ToggleChannel3 => write!(f, "ToggleChannel3"),
-            MyActions::ToggleChannel4 => write!(f, "ToggleChannel4"),
-            MyActions::ToggleChannel5 => write!(f, "ToggleChannel5"),
+            MyActions::ToggleChannel(channel) => write!(f, "ToggleChannel{}", channel),
             MyActions::UseStrip2 => write!(f, "UseStrip2"),
         }
     }
@@ -63,7 +53,10 @@ use MyTags::*;
 
 impl Display for MyTags {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "")
+        match self {
+            MyTags::ToggleChannels => write!(f, "ToggleChannels"),
+            MyTags::Volume => write!(f, "Volume"),
+        }
     }
 }
 
@@ -77,6 +70,9 @@ impl ActionHandler<MyActions, MyTags> for Handler {
                     println!("Hello. Press Alt+A -> E -> X -> I -> T to exit.")
                 }
                 Event::System(KeyboardUnhooked) => println!("Exiting..."),
+                Event::System(OverBudget(elapsed)) => {
+                    println!("Handling a key press took {:?}", elapsed)
+                }
                 Event::Single(action) => println!("Received action: {}", action),
                 Event::Multi(tag, actions) => {
                     println!("Received actions ({:?}): {:?}", tag, actions)
@@ -86,65 +82,34 @@ impl ActionHandler<MyActions, MyTags> for Handler {
     }
 }
 
-fn define_mappings() -> Vec<Vec<Mapping<MyActions, MyTags>>> {
-    vec![
-        // Alt+A -> E -> X -> I -> T
-        vec![
-            t!(KeyA, ModAlt),
-            t!(KeyE),
-            t!(KeyX),
-            t!(KeyI),
-            shutdown!(KeyT), // Unhook the keyboard and exit.
-        ],
-        // Alt+A -> Q
-        vec![
-            t!(KeyA, ModAlt),
-            aot!(KeyQ, Princess), // Action on timeout.
-        ],
-        // Alt+A -> W
-        vec![
-            t!(KeyA, ModAlt),
-            a!(KeyW, Kenny), // Immediate action.
-        ],
-        // Alt+A -> S -> 2 -> [12345]*
-        vec![
-            t!(KeyA, ModAlt),
-            t!(KeyS),
-            aot!(Key2, UseStrip2),
-            c!(
-                [
-                    key_aot!(Key1, ToggleChannel1),
-                    key_aot!(Key2, ToggleChannel2),
-                    key_aot!(Key3, ToggleChannel3),
-                    key_aot!(Key4, ToggleChannel4),
-                    key_aot!(Key5, ToggleChannel5)
-                ],
-                ToggleChannels
-            ),
-        ],
-        // Alt+A -> [12345]*
-        vec![
-            t!(KeyA, ModAlt),
-            c!(
-                [
-                    key_aot!(Key1, ToggleChannel1),
-                    key_aot!(Key2, ToggleChannel2),
-                    key_aot!(Key3, ToggleChannel3),
-                    key_aot!(Key4, ToggleChannel4),
-                    key_aot!(Key5, ToggleChannel5)
-                ],
-                ToggleChannels
-            ),
-        ],
-        // Alt+A -> [JK]*
-        vec![
-            t!(KeyA, ModAlt),
-            c!([key_a!(KeyJ, VolumeDown), key_a!(KeyK, VolumeUp)], Volume),
-        ],
-    ]
+fn define_mappings() -> Mappings<MyActions, MyTags> {
+    let channels = Fragment::new(
+        "channels",
+        keymap! { "[1-5]*" as ToggleChannels => ToggleChannel(n) on timeout },
+    );
+    let mappings: Vec<Vec<Mapping<MyActions, MyTags>>> = keymap! {
+        "<A-a> e x i t" => shutdown,      // Unhook the keyboard and exit.
+        "<A-a> q" => Princess on timeout, // Action on timeout.
+        "<A-a> w" => Kenny,               // Immediate action.
+        "<A-a> s 2" => UseStrip2 on timeout,
+        "<A-a> [jk]*" as Volume => { j => VolumeDown, k => VolumeUp },
+    };
+
+    // The same channel choice is available right after Alt+A and after picking strip 2.
+    Mappings::new(mappings)
+        .mount(&[alt!(KeyA)], &channels)
+        .mount(&[alt!(KeyA), key!(KeyS), key!(Key2)], &channels)
 }
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--cheat-sheet") {
+        print!(
+            "{}",
+            CheatSheet::new("Demo", &define_mappings()).to_markdown()
+        );
+        return;
+    }
+
     let handler = Handler;
     let app = KeyboardHook::new(define_mappings(), Box::new(handler));
 
@@ -0,0 +1,152 @@
+//! Drives the engine with keymaps and key events decoded from the fuzzer's bytes, checking that
+//! it never panics, that nothing is left pending once a timeout passed, and that only keys
+//! leading somewhere in the trie are suppressed.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use keyboard_hook::engine::Engine;
+use keyboard_hook::mapping_trie::Cursor;
+use keyboard_hook::mapping_trie::MappingTrie;
+use keyboard_hook::types::Behaviour;
+use keyboard_hook::types::Behaviours;
+use keyboard_hook::types::FlushPolicy;
+use keyboard_hook::types::HookAction;
+use keyboard_hook::types::Key;
+use keyboard_hook::types::KeyPress;
+use keyboard_hook::types::Mapping;
+use keyboard_hook::types::Modifier;
+use libfuzzer_sys::fuzz_target;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A handful of keys, so sequences share prefixes and choices overlap, along with any other key.
+#[derive(Arbitrary, Debug)]
+enum FuzzKey {
+    Letter(bool),
+    Digit(u8),
+    AltLetter,
+    ShiftDigit(u8),
+    Any(u8),
+}
+
+impl FuzzKey {
+    fn key_press(&self) -> KeyPress {
+        match self {
+            FuzzKey::Letter(second) => Key::from_u8(if *second { b'B' } else { b'A' }).into(),
+            FuzzKey::Digit(digit) => Key::from_u8(b'0' + digit % 4).into(),
+            FuzzKey::AltLetter => KeyPress::Mod(Key::KeyA, Modifier::ModAlt),
+            FuzzKey::ShiftDigit(digit) => {
+                KeyPress::Mod(Key::from_u8(b'0' + digit % 4), Modifier::ModShift)
+            }
+            FuzzKey::Any(key) => Key::from_u8(*key).into(),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum FuzzBehaviour {
+    Timeout,
+    Action(u8),
+    ActionOnTimeout(u8),
+    Shutdown,
+}
+
+impl FuzzBehaviour {
+    fn behaviour(&self, key: KeyPress) -> Behaviour<u8> {
+        match self {
+            FuzzBehaviour::Timeout => Behaviour::Timeout(key),
+            FuzzBehaviour::Action(action) => Behaviour::Action(key, *action),
+            FuzzBehaviour::ActionOnTimeout(action) => Behaviour::ActionOnTimeout(key, *action),
+            FuzzBehaviour::Shutdown => Behaviour::Shutdown(key),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum FuzzMapping {
+    Single(FuzzKey, FuzzBehaviour),
+    Choice(u8, Vec<(FuzzKey, FuzzBehaviour)>),
+}
+
+impl FuzzMapping {
+    fn mapping(&self) -> Mapping<u8, u8> {
+        match self {
+            FuzzMapping::Single(key, behaviour) => {
+                Mapping::Single(behaviour.behaviour(key.key_press()))
+            }
+            FuzzMapping::Choice(tag, keys) => Mapping::Choice(
+                Behaviours(
+                    keys.iter()
+                        .map(|(key, behaviour)| behaviour.behaviour(key.key_press()))
+                        .collect(),
+                ),
+                *tag,
+            ),
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+enum FuzzEvent {
+    Press(FuzzKey),
+    /// Moves the clock by the milliseconds and ticks the engine if the deadline passed.
+    Advance(u16),
+    Stop(bool),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    keymap: Vec<Vec<FuzzMapping>>,
+    events: Vec<FuzzEvent>,
+}
+
+fuzz_target!(|input: Input| {
+    let sequences: Vec<Vec<Mapping<u8, u8>>> = input
+        .keymap
+        .iter()
+        .map(|sequence| sequence.iter().map(FuzzMapping::mapping).collect())
+        .collect();
+    let (trie, _) = MappingTrie::new(&sequences.into());
+    let mut engine = Engine::from_trie(trie.clone()).with_timeout(Duration::from_millis(650));
+    let mut now = Instant::now();
+
+    for event in &input.events {
+        match event {
+            FuzzEvent::Press(key) => {
+                let key = key.key_press();
+                let node = trie.node(engine.cursor());
+                let leads_somewhere = node.edges().any(|(edge, _)| *edge == key)
+                    || node.repeatable_keys().contains(&key);
+
+                let (hook_action, _) = engine.feed(&key, now);
+
+                if hook_action == HookAction::Suppress {
+                    assert!(leads_somewhere, "suppressed {} at node {}", key, node.id());
+                }
+            }
+            FuzzEvent::Advance(millis) => {
+                now += Duration::from_millis(u64::from(*millis));
+
+                if engine.next_deadline().is_some_and(|deadline| deadline <= now) {
+                    engine.tick(now);
+
+                    assert_eq!(engine.next_deadline(), None);
+                    assert_eq!(engine.pending(), None);
+                    assert_eq!(engine.cursor(), Cursor::root());
+                }
+            }
+            FuzzEvent::Stop(flush) => {
+                let policy = if *flush {
+                    FlushPolicy::Flush
+                } else {
+                    FlushPolicy::Discard
+                };
+                engine.stop(policy);
+
+                assert!(engine.is_stopped());
+                assert_eq!(engine.next_deadline(), None);
+            }
+        }
+    }
+});
@@ -0,0 +1,548 @@
+use crate::sequence;
+use crate::sequence::KeyPress;
+use crate::sequence::Step;
+use proc_macro2::Group;
+use proc_macro2::TokenStream;
+use proc_macro2::TokenTree;
+use quote::quote;
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::parse::Parse;
+use syn::parse::ParseStream;
+use syn::Expr;
+use syn::Ident;
+use syn::LitStr;
+use syn::Token;
+
+#[derive(Clone)]
+enum Behaviour {
+    Timeout,
+    Action(TokenStream),
+    ActionOnTimeout(TokenStream),
+    Shutdown,
+}
+
+impl Behaviour {
+    fn fires_immediately(&self) -> bool {
+        matches!(self, Behaviour::Action(_) | Behaviour::Shutdown)
+    }
+
+    fn to_tokens(&self, key: &KeyPress) -> TokenStream {
+        let key = sequence::tokens(key);
+
+        match self {
+            Behaviour::Timeout => quote! { ::keyboard_hook::types::Behaviour::Timeout(#key) },
+            Behaviour::Action(action) => {
+                quote! { ::keyboard_hook::types::Behaviour::Action(#key, #action) }
+            }
+            Behaviour::ActionOnTimeout(action) => {
+                quote! { ::keyboard_hook::types::Behaviour::ActionOnTimeout(#key, #action) }
+            }
+            Behaviour::Shutdown => quote! { ::keyboard_hook::types::Behaviour::Shutdown(#key) },
+        }
+    }
+
+    /// Replaces every `n` in the action with the value of the given key.
+    fn instantiate(&self, key: &KeyPress) -> Behaviour {
+        let value = sequence::template_value(key);
+
+        match self {
+            Behaviour::Action(action) => Behaviour::Action(substitute(action.clone(), &value)),
+            Behaviour::ActionOnTimeout(action) => {
+                Behaviour::ActionOnTimeout(substitute(action.clone(), &value))
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+fn substitute(tokens: TokenStream, value: &TokenStream) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|token| match token {
+            TokenTree::Ident(ident) if ident == "n" => value.clone(),
+            TokenTree::Group(group) => {
+                let mut substituted =
+                    Group::new(group.delimiter(), substitute(group.stream(), value));
+                substituted.set_span(group.span());
+                TokenTree::Group(substituted).into_token_stream()
+            }
+            other => other.into_token_stream(),
+        })
+        .collect()
+}
+
+fn peek_keyword(input: ParseStream, keyword: &str) -> bool {
+    let fork = input.fork();
+
+    match fork.parse::<Ident>() {
+        Ok(ident) => ident == keyword && (fork.is_empty() || fork.peek(Token![,])),
+        Err(_) => false,
+    }
+}
+
+impl Parse for Behaviour {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if peek_keyword(input, "timeout") {
+            input.parse::<Ident>()?;
+            return Ok(Behaviour::Timeout);
+        }
+
+        if peek_keyword(input, "shutdown") {
+            input.parse::<Ident>()?;
+            return Ok(Behaviour::Shutdown);
+        }
+
+        let action = input.parse::<Expr>()?.into_token_stream();
+
+        if input.peek(Ident) {
+            let on: Ident = input.parse()?;
+            let timeout: Ident = input.parse()?;
+
+            if on != "on" || timeout != "timeout" {
+                return Err(syn::Error::new(on.span(), "expected `on timeout`"));
+            }
+
+            return Ok(Behaviour::ActionOnTimeout(action));
+        }
+
+        Ok(Behaviour::Action(action))
+    }
+}
+
+enum Spec {
+    Single(Behaviour),
+    Template(Behaviour),
+    PerKey(Vec<(LitStr, Behaviour)>),
+}
+
+struct Entry {
+    notation: LitStr,
+    steps: Vec<Step>,
+    tag: Option<Expr>,
+    spec: Spec,
+}
+
+fn parse_choice_key(input: ParseStream) -> syn::Result<LitStr> {
+    let lookahead = input.lookahead1();
+
+    if lookahead.peek(LitStr) {
+        input.parse()
+    } else if lookahead.peek(Ident) {
+        let ident: Ident = input.parse()?;
+        Ok(LitStr::new(&ident.to_string(), ident.span()))
+    } else if lookahead.peek(syn::LitInt) {
+        let int: syn::LitInt = input.parse()?;
+        Ok(LitStr::new(&int.to_string(), int.span()))
+    } else {
+        Err(lookahead.error())
+    }
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let notation: LitStr = input.parse()?;
+        let steps =
+            sequence::parse(&notation.value()).map_err(|e| syn::Error::new(notation.span(), e))?;
+
+        let tag = if input.peek(Token![as]) {
+            input.parse::<Token![as]>()?;
+            Some(Expr::parse_without_eager_brace(input)?)
+        } else {
+            None
+        };
+
+        input.parse::<Token![=>]>()?;
+        let is_choice = matches!(steps.last(), Some(Step::Choice(_)));
+
+        let spec = if !is_choice {
+            Spec::Single(input.parse()?)
+        } else if input.peek(syn::token::Brace) {
+            let content;
+            syn::braced!(content in input);
+            let mut keys = vec![];
+
+            while !content.is_empty() {
+                let key = parse_choice_key(&content)?;
+                content.parse::<Token![=>]>()?;
+                keys.push((key, content.parse()?));
+
+                if !content.is_empty() {
+                    content.parse::<Token![,]>()?;
+                }
+            }
+
+            Spec::PerKey(keys)
+        } else {
+            Spec::Template(input.parse()?)
+        };
+
+        match (is_choice, &tag) {
+            (true, None) => Err(syn::Error::new(
+                notation.span(),
+                format!(
+                    "a choice needs a tag, e.g. `\"{}\" as Tag => ...`",
+                    notation.value()
+                ),
+            )),
+            (false, Some(tag)) => Err(syn::Error::new_spanned(tag, "only choices take a tag")),
+            _ => Ok(Entry {
+                notation,
+                steps,
+                tag,
+                spec,
+            }),
+        }
+    }
+}
+
+impl Entry {
+    fn prefix(&self) -> Vec<KeyPress> {
+        self.steps
+            .iter()
+            .filter_map(|step| match step {
+                Step::Key(key) => Some(*key),
+                Step::Choice(_) => None,
+            })
+            .collect()
+    }
+
+    /// Resolves the per-key behaviours of a choice.
+    fn choice_behaviours(&self, keys: &[KeyPress]) -> syn::Result<Vec<(KeyPress, Behaviour)>> {
+        match &self.spec {
+            Spec::Template(behaviour) => Ok(keys
+                .iter()
+                .map(|key| (*key, behaviour.instantiate(key)))
+                .collect()),
+            Spec::PerKey(per_key) => {
+                let mut result = vec![];
+
+                for (lit, behaviour) in per_key {
+                    let key = match sequence::parse(&lit.value()) {
+                        Ok(steps) => match steps.as_slice() {
+                            [Step::Key(key)] if keys.contains(key) => *key,
+                            _ => {
+                                return Err(syn::Error::new(
+                                    lit.span(),
+                                    format!("`{}` is not one of the choice's keys", lit.value()),
+                                ))
+                            }
+                        },
+                        Err(e) => return Err(syn::Error::new(lit.span(), e)),
+                    };
+
+                    if result.iter().any(|(k, _)| *k == key) {
+                        return Err(syn::Error::new(
+                            lit.span(),
+                            format!("`{}` is mapped twice", key),
+                        ));
+                    }
+
+                    result.push((key, behaviour.clone()));
+                }
+
+                if let Some(missing) = keys.iter().find(|k| !result.iter().any(|(r, _)| r == *k)) {
+                    return Err(syn::Error::new(
+                        self.notation.span(),
+                        format!("`{}` of `{}` is not mapped", missing, self.notation.value()),
+                    ));
+                }
+
+                Ok(keys
+                    .iter()
+                    .map(|key| result.iter().find(|(k, _)| k == key).unwrap().clone())
+                    .collect())
+            }
+            Spec::Single(_) => unreachable!("Single spec on a choice entry."),
+        }
+    }
+}
+
+pub(crate) struct Keymap {
+    entries: Vec<Entry>,
+}
+
+impl Parse for Keymap {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let entries = input.parse_terminated(Entry::parse, Token![,])?;
+
+        Ok(Keymap {
+            entries: entries.into_iter().collect(),
+        })
+    }
+}
+
+fn combine(errors: &mut Option<syn::Error>, error: syn::Error) {
+    match errors {
+        Some(errors) => errors.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+/// Which entry put a key under a given prefix, and whether it did so as part of a choice.
+struct Child {
+    key: KeyPress,
+    entry: usize,
+    from_choice: bool,
+}
+
+impl Keymap {
+    /// Finds sequences that can never be matched by the mapping trie: duplicates, sequences
+    /// hidden behind an immediate action, and choices sharing keys with their siblings.
+    fn validate(&self) -> syn::Result<HashMap<Vec<KeyPress>, usize>> {
+        let mut errors = None;
+        let mut singles: HashMap<Vec<KeyPress>, usize> = HashMap::new();
+        let mut children: HashMap<Vec<KeyPress>, Vec<Child>> = HashMap::new();
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let prefix = entry.prefix();
+
+            match entry.steps.last() {
+                Some(Step::Key(_)) => {
+                    if let Some(other) = singles.get(&prefix) {
+                        combine(
+                            &mut errors,
+                            syn::Error::new(
+                                entry.notation.span(),
+                                format!(
+                                    "`{}` is already mapped by `{}`",
+                                    sequence::to_string(&entry.steps),
+                                    self.entries[*other].notation.value()
+                                ),
+                            ),
+                        );
+                    } else {
+                        singles.insert(prefix.clone(), i);
+                    }
+                }
+                Some(Step::Choice(keys)) => {
+                    for key in keys {
+                        children.entry(prefix.clone()).or_default().push(Child {
+                            key: *key,
+                            entry: i,
+                            from_choice: true,
+                        });
+                    }
+                }
+                None => {}
+            }
+
+            for (depth, key) in prefix.iter().enumerate() {
+                children
+                    .entry(prefix[..depth].to_vec())
+                    .or_default()
+                    .push(Child {
+                        key: *key,
+                        entry: i,
+                        from_choice: false,
+                    });
+            }
+        }
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let prefix = entry.prefix();
+            let len = match entry.steps.last() {
+                Some(Step::Choice(_)) => prefix.len(),
+                _ => prefix.len().saturating_sub(1),
+            };
+
+            for depth in 1..=len {
+                if let Some(other) = singles.get(&prefix[..depth]) {
+                    if let Spec::Single(behaviour) = &self.entries[*other].spec {
+                        if behaviour.fires_immediately() && *other != i {
+                            combine(
+                                &mut errors,
+                                syn::Error::new(
+                                    entry.notation.span(),
+                                    format!(
+                                        "`{}` can never be reached, because `{}` fires immediately",
+                                        entry.notation.value(),
+                                        self.entries[*other].notation.value()
+                                    ),
+                                ),
+                            );
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut prefixes: Vec<_> = children.keys().cloned().collect();
+        prefixes.sort_by_key(|p| p.len());
+
+        for prefix in prefixes {
+            let siblings = &children[&prefix];
+
+            for (i, child) in siblings.iter().enumerate() {
+                let conflict = siblings[..i].iter().find(|other| {
+                    other.key == child.key
+                        && other.entry != child.entry
+                        && (other.from_choice || child.from_choice)
+                });
+
+                if let Some(other) = conflict {
+                    combine(
+                        &mut errors,
+                        syn::Error::new(
+                            self.entries[child.entry].notation.span(),
+                            format!(
+                                "`{}` conflicts with `{}` on key `{}`",
+                                self.entries[child.entry].notation.value(),
+                                self.entries[other.entry].notation.value(),
+                                child.key
+                            ),
+                        ),
+                    );
+                }
+            }
+        }
+
+        match errors {
+            Some(errors) => Err(errors),
+            None => Ok(singles),
+        }
+    }
+
+    /// Validates the keymap and resolves the behaviour tokens of every key of every sequence.
+    pub(crate) fn resolve(&self) -> syn::Result<Vec<ResolvedSequence>> {
+        let singles = self.validate()?;
+        let mut sequences = vec![];
+
+        for entry in &self.entries {
+            let prefix = entry.prefix();
+            let mut keys = vec![];
+
+            for (depth, key) in prefix.iter().enumerate() {
+                let behaviour = match singles.get(&prefix[..=depth]) {
+                    Some(other) => match &self.entries[*other].spec {
+                        Spec::Single(behaviour) => behaviour.clone(),
+                        _ => Behaviour::Timeout,
+                    },
+                    None => Behaviour::Timeout,
+                };
+
+                keys.push((*key, behaviour.to_tokens(key)));
+            }
+
+            let choice = match entry.steps.last() {
+                Some(Step::Choice(choice_keys)) => {
+                    let behaviours = entry
+                        .choice_behaviours(choice_keys)?
+                        .into_iter()
+                        .map(|(key, behaviour)| (key, behaviour.to_tokens(&key)))
+                        .collect();
+
+                    Some((entry.tag.to_token_stream(), behaviours))
+                }
+                _ => None,
+            };
+
+            sequences.push(ResolvedSequence { keys, choice });
+        }
+
+        Ok(sequences)
+    }
+
+    pub(crate) fn expand(&self) -> syn::Result<TokenStream> {
+        let sequences = self.resolve()?.into_iter().map(|sequence| {
+            let mut mappings: Vec<TokenStream> = sequence
+                .keys
+                .iter()
+                .map(
+                    |(_, behaviour)| quote! { ::keyboard_hook::types::Mapping::Single(#behaviour) },
+                )
+                .collect();
+
+            if let Some((tag, behaviours)) = sequence.choice {
+                let behaviours = behaviours.iter().map(|(_, behaviour)| behaviour);
+
+                mappings.push(quote! {
+                    ::keyboard_hook::types::Mapping::Choice(
+                        ::keyboard_hook::types::Behaviours(::std::vec![#(#behaviours),*]),
+                        #tag,
+                    )
+                });
+            }
+
+            quote! { ::std::vec![#(#mappings),*] }
+        });
+
+        Ok(quote! { ::std::vec![#(#sequences),*] })
+    }
+}
+
+/// A validated sequence with the behaviour tokens of its keys, optionally ending with a choice
+/// (tag and per-key behaviours).
+pub(crate) struct ResolvedSequence {
+    pub keys: Vec<(KeyPress, TokenStream)>,
+    pub choice: Option<(TokenStream, Vec<(KeyPress, TokenStream)>)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: &str) -> Result<String, String> {
+        let keymap: Keymap = syn::parse_str(input).map_err(|e| e.to_string())?;
+
+        keymap
+            .expand()
+            .map(|tokens| tokens.to_string())
+            .map_err(|e| {
+                e.into_iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+    }
+
+    #[test]
+    fn should_reuse_behaviours_of_prefix_sequences() {
+        let expanded = expand(
+            r#"
+            "<A-a> s 2" => UseStrip2 on timeout,
+            "<A-a> s 2 [1-2]*" as Channels => Channel(n) on timeout,
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(expanded.matches("ActionOnTimeout").count(), 4);
+        assert!(expanded.contains("Channel (2)"));
+    }
+
+    #[test]
+    fn should_reject_sequences_behind_immediate_actions() {
+        let error = expand(r#""<A-a> w" => Kenny, "<A-a> w x" => Princess"#).unwrap_err();
+
+        assert!(error.contains("can never be reached"), "{}", error);
+    }
+
+    #[test]
+    fn should_reject_duplicate_sequences() {
+        let error = expand(r#""<A-a> w" => Kenny, "<A-a> w" => Princess"#).unwrap_err();
+
+        assert!(error.contains("already mapped"), "{}", error);
+    }
+
+    #[test]
+    fn should_reject_choices_overlapping_other_keys() {
+        let error =
+            expand(r#""<A-a> 2" => Kenny, "<A-a> [1-5]*" as Chans => Chan(n)"#).unwrap_err();
+
+        assert!(error.contains("conflicts with"), "{}", error);
+    }
+
+    #[test]
+    fn should_require_every_choice_key_to_be_mapped() {
+        let error = expand(r#""<A-a> [jk]*" as Volume => { j => VolumeDown }"#).unwrap_err();
+
+        assert!(error.contains("is not mapped"), "{}", error);
+    }
+
+    #[test]
+    fn should_require_a_tag_on_choices_only() {
+        assert!(expand(r#""<A-a> [jk]*" => Volume"#).is_err());
+        assert!(expand(r#""<A-a> j" as Volume => VolumeDown"#).is_err());
+    }
+}
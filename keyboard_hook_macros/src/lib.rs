@@ -0,0 +1,56 @@
+mod keymap;
+mod sequence;
+mod static_trie;
+
+use proc_macro::TokenStream;
+
+/// Builds a `Vec<Vec<Mapping<A, T>>>` from a compact sequence notation.
+///
+/// ```ignore
+/// let mappings: Vec<Vec<Mapping<MyActions, MyTags>>> = keymap! {
+///     "<A-a> e x i t" => shutdown,                 // Unhook the keyboard.
+///     "<A-a> q" => Princess on timeout,            // Action on timeout.
+///     "<A-a> w" => Kenny,                          // Immediate action.
+///     "<A-a> s 2" => UseStrip2 on timeout,
+///     "<A-a> s 2 [1-5]*" as ToggleChannels => ToggleChannel(n) on timeout,
+///     "<A-a> [jk]*" as Volume => { j => VolumeDown, k => VolumeUp },
+/// };
+/// ```
+///
+/// Keys are `a`-`z` and `0`-`9`, optionally with `A` (Alt) and/or `S` (Shift) modifiers, e.g.
+/// `<A-S-a>`. A sequence may end with a repeatable choice `[...]*`, which needs a tag (`as Tag`)
+/// and either a template action, where `n` is replaced with the pressed digit (or char), or a
+/// per-key list of behaviours.
+///
+/// Keys leading up to the last one only trigger a timeout, unless another entry maps that
+/// exact prefix. Conflicting sequences are reported as compile errors.
+#[proc_macro]
+pub fn keymap(input: TokenStream) -> TokenStream {
+    let keymap = syn::parse_macro_input!(input as keymap::Keymap);
+
+    match keymap.expand() {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Like [`keymap!`], but lays the mappings out at compile time as a
+/// `keyboard_hook::static_trie::StaticMappingTrie`, which can be stored in a `static`:
+///
+/// ```ignore
+/// static MAPPINGS: StaticMappingTrie<MyActions, MyTags> = static_keymap! {
+///     "<A-a> w" => Kenny,
+///     "<A-a> [1-5]*" as ToggleChannels => ToggleChannel(n) on timeout,
+/// };
+/// ```
+///
+/// Actions and tags have to be constant expressions.
+#[proc_macro]
+pub fn static_keymap(input: TokenStream) -> TokenStream {
+    let keymap = syn::parse_macro_input!(input as keymap::Keymap);
+
+    match static_trie::expand(&keymap) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
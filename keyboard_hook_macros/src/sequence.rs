@@ -0,0 +1,38 @@
+use proc_macro2::Span;
+use proc_macro2::TokenStream;
+use quote::format_ident;
+use quote::quote;
+use quote::ToTokens;
+
+pub(crate) use keyboard_hook_notation::parse;
+pub(crate) use keyboard_hook_notation::to_string;
+pub(crate) use keyboard_hook_notation::KeyPress;
+use keyboard_hook_notation::Modifier;
+pub(crate) use keyboard_hook_notation::Step;
+
+/// The `keyboard_hook::types::KeyPress` expression for the key.
+pub(crate) fn tokens(key: &KeyPress) -> TokenStream {
+    let name = format_ident!("Key{}", key.key.to_ascii_uppercase());
+    let modifier = match key.modifier {
+        Modifier::NoMod => format_ident!("NoMod"),
+        Modifier::ModAlt => format_ident!("ModAlt"),
+        Modifier::ModShift => format_ident!("ModShift"),
+        Modifier::ModAltShift => format_ident!("ModAltShift"),
+    };
+
+    quote! {
+        ::keyboard_hook::types::KeyPress::Mod(
+            ::keyboard_hook::types::Key::#name,
+            ::keyboard_hook::types::Modifier::#modifier,
+        )
+    }
+}
+
+/// The value `n` stands for in a choice template: digits become integer literals, letters become
+/// char literals.
+pub(crate) fn template_value(key: &KeyPress) -> TokenStream {
+    match key.key.to_digit(10) {
+        Some(digit) => syn::LitInt::new(&digit.to_string(), Span::call_site()).into_token_stream(),
+        None => syn::LitChar::new(key.key, Span::call_site()).into_token_stream(),
+    }
+}
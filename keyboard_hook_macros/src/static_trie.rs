@@ -0,0 +1,125 @@
+use crate::keymap::Keymap;
+use crate::sequence;
+use crate::sequence::KeyPress;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+struct Node {
+    behaviour: Option<TokenStream>,
+    tag: Option<TokenStream>,
+    edges: Vec<(KeyPress, usize)>,
+}
+
+impl Node {
+    fn new(behaviour: Option<TokenStream>, tag: Option<TokenStream>) -> Self {
+        Self {
+            behaviour,
+            tag,
+            edges: vec![],
+        }
+    }
+
+    fn child(&self, key: &KeyPress) -> Option<usize> {
+        self.edges.iter().find(|(k, _)| k == key).map(|(_, i)| *i)
+    }
+}
+
+/// Lays the keymap out as a flat list of nodes, the root being the first one. Every key of a
+/// choice gets its own node with edges to all the keys of the choice, so repeating it is just
+/// another step down the trie.
+fn build(keymap: &Keymap) -> syn::Result<Vec<Node>> {
+    let mut nodes = vec![Node::new(None, None)];
+
+    for sequence in keymap.resolve()? {
+        let mut node = 0;
+
+        for (key, behaviour) in &sequence.keys {
+            node = match nodes[node].child(key) {
+                Some(next) => next,
+                None => {
+                    nodes.push(Node::new(Some(behaviour.clone()), None));
+                    let next = nodes.len() - 1;
+                    nodes[node].edges.push((*key, next));
+                    next
+                }
+            };
+        }
+
+        if let Some((tag, behaviours)) = sequence.choice {
+            let first = nodes.len();
+
+            for (key, behaviour) in &behaviours {
+                let next = nodes.len();
+                nodes.push(Node::new(Some(behaviour.clone()), Some(tag.clone())));
+                nodes[node].edges.push((*key, next));
+            }
+
+            let edges: Vec<_> = behaviours
+                .iter()
+                .enumerate()
+                .map(|(i, (key, _))| (*key, first + i))
+                .collect();
+
+            for choice_node in &mut nodes[first..] {
+                choice_node.edges = edges.clone();
+            }
+        }
+    }
+
+    for node in &mut nodes {
+        node.edges.sort_by_key(|(key, _)| *key);
+    }
+
+    Ok(nodes)
+}
+
+pub(crate) fn expand(keymap: &Keymap) -> syn::Result<TokenStream> {
+    let nodes = build(keymap)?.into_iter().map(|node| {
+        let behaviour = match node.behaviour {
+            Some(behaviour) => quote! { ::std::option::Option::Some(#behaviour) },
+            None => quote! { ::std::option::Option::None },
+        };
+        let tag = match node.tag {
+            Some(tag) => quote! { ::std::option::Option::Some(#tag) },
+            None => quote! { ::std::option::Option::None },
+        };
+        let edges = node.edges.iter().map(|(key, i)| {
+            let key = sequence::tokens(key);
+            quote! { (#key, #i) }
+        });
+
+        quote! {
+            ::keyboard_hook::static_trie::StaticNode::new(#behaviour, #tag, &[#(#edges),*])
+        }
+    });
+
+    Ok(quote! {
+        ::keyboard_hook::static_trie::StaticMappingTrie::new(&[#(#nodes),*])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_link_choice_keys_to_each_other() {
+        let keymap: Keymap = syn::parse_str(
+            r#"
+            "<A-a> q" => Princess on timeout,
+            "<A-a> [12]*" as Chans => Chan(n) on timeout,
+            "#,
+        )
+        .unwrap();
+
+        let nodes = build(&keymap).unwrap();
+        let edges = |i: usize| nodes[i].edges.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(edges(0), vec![1]);
+        assert_eq!(edges(1), vec![3, 4, 2]);
+        assert_eq!(edges(3), vec![3, 4]);
+        assert_eq!(edges(4), vec![3, 4]);
+        assert!(nodes[3].tag.is_some());
+    }
+}
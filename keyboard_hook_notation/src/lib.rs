@@ -0,0 +1,291 @@
+use std::fmt;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+pub enum Modifier {
+    NoMod,
+    ModAlt,
+    ModShift,
+    ModAltShift,
+}
+
+/// A single key of the sequence notation. Letters are kept in lower case, the way they're written.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash)]
+pub struct KeyPress {
+    pub key: char,
+    pub modifier: Modifier,
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.modifier {
+            Modifier::NoMod => write!(f, "{}", self.key),
+            Modifier::ModAlt => write!(f, "<A-{}>", self.key),
+            Modifier::ModShift => write!(f, "<S-{}>", self.key),
+            Modifier::ModAltShift => write!(f, "<A-S-{}>", self.key),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Step {
+    Key(KeyPress),
+    Choice(Vec<KeyPress>),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Key(key) => write!(f, "{}", key),
+            Step::Choice(keys) => {
+                let keys = keys.iter().map(|k| k.to_string()).collect::<String>();
+                write!(f, "[{}]*", keys)
+            }
+        }
+    }
+}
+
+pub fn to_string(steps: &[Step]) -> String {
+    steps
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_key(key: char) -> Result<char, String> {
+    if key.is_ascii_alphanumeric() {
+        Ok(key.to_ascii_lowercase())
+    } else {
+        Err(format!(
+            "`{}` is not a mappable key (expected a-z or 0-9)",
+            key
+        ))
+    }
+}
+
+fn parse_single(token: &str) -> Result<char, String> {
+    let mut chars = token.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(key), None) => parse_key(key),
+        _ => Err(format!("`{}` is not a single key", token)),
+    }
+}
+
+fn parse_modified(token: &str) -> Result<KeyPress, String> {
+    let parts: Vec<&str> = token[1..token.len() - 1].split('-').collect();
+    let (modifiers, key) = parts.split_at(parts.len() - 1);
+    let key = parse_single(key[0])?;
+
+    let mut alt = false;
+    let mut shift = false;
+
+    for modifier in modifiers {
+        match *modifier {
+            "A" | "a" if !alt => alt = true,
+            "S" | "s" if !shift => shift = true,
+            _ => {
+                return Err(format!(
+                    "`{}` has an unknown modifier `{}`",
+                    token, modifier
+                ))
+            }
+        }
+    }
+
+    let modifier = match (alt, shift) {
+        (true, true) => Modifier::ModAltShift,
+        (true, false) => Modifier::ModAlt,
+        (false, true) => Modifier::ModShift,
+        (false, false) => return Err(format!("`{}` has no modifier", token)),
+    };
+
+    Ok(KeyPress { key, modifier })
+}
+
+fn parse_choice(token: &str) -> Result<Vec<KeyPress>, String> {
+    let Some(class) = token.strip_suffix('*') else {
+        return Err(format!("choices are always repeatable, write `{}*`", token));
+    };
+
+    let chars: Vec<char> = class[1..class.len() - 1].chars().collect();
+    let mut keys: Vec<KeyPress> = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let range = if i + 2 < chars.len() && chars[i + 1] == '-' {
+            let (from, to) = (parse_key(chars[i])?, parse_key(chars[i + 2])?);
+
+            if from > to || from.is_ascii_digit() != to.is_ascii_digit() {
+                return Err(format!("`{}-{}` is not a valid range", from, to));
+            }
+
+            i += 3;
+            from..=to
+        } else {
+            let key = parse_key(chars[i])?;
+            i += 1;
+            key..=key
+        };
+
+        for key in range {
+            let key_press = KeyPress {
+                key,
+                modifier: Modifier::NoMod,
+            };
+
+            if keys.contains(&key_press) {
+                return Err(format!("`{}` appears twice in `{}`", key, token));
+            }
+
+            keys.push(key_press);
+        }
+    }
+
+    if keys.is_empty() {
+        return Err("a choice needs at least one key".to_string());
+    }
+
+    Ok(keys)
+}
+
+/// Parses a single key press, e.g. `s` or `<A-a>`.
+pub fn parse_key_press(token: &str) -> Result<KeyPress, String> {
+    if token.starts_with('<') && token.ends_with('>') && token.len() > 2 {
+        parse_modified(token)
+    } else {
+        Ok(KeyPress {
+            key: parse_single(token)?,
+            modifier: Modifier::NoMod,
+        })
+    }
+}
+
+/// Parses the sequence notation, e.g. `<A-a> s 2 [1-5]*`.
+pub fn parse(notation: &str) -> Result<Vec<Step>, String> {
+    let mut steps = vec![];
+
+    for token in notation.split_whitespace() {
+        if let Some(Step::Choice(_)) = steps.last() {
+            return Err("a choice must be the last step of a sequence".to_string());
+        }
+
+        let step = if token.starts_with('[') && token.trim_end_matches('*').ends_with(']') {
+            Step::Choice(parse_choice(token)?)
+        } else {
+            Step::Key(parse_key_press(token)?)
+        };
+
+        steps.push(step);
+    }
+
+    if steps.is_empty() {
+        return Err("a sequence needs at least one key".to_string());
+    }
+
+    Ok(steps)
+}
+
+/// Replaces every standalone `n` in the text of a choice template with the key, for templates
+/// kept as text rather than tokens. An `n` escaped with a backslash, as in `\n`, is left alone.
+pub fn substitute(template: &str, key: char) -> String {
+    let chars: Vec<char> = template.chars().collect();
+    let is_word = |i: usize| {
+        chars
+            .get(i)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+    };
+
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let after_word = i > 0 && (is_word(i - 1) || chars[i - 1] == '\\');
+            let standalone = *c == 'n' && !after_word && !is_word(i + 1);
+
+            if standalone {
+                key
+            } else {
+                *c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: char) -> KeyPress {
+        KeyPress {
+            key,
+            modifier: Modifier::NoMod,
+        }
+    }
+
+    #[test]
+    fn should_parse_keys_with_and_without_modifiers() {
+        let steps = parse("<A-a> <S-B> <A-S-1> s").unwrap();
+
+        assert_eq!(
+            steps,
+            vec![
+                Step::Key(KeyPress {
+                    key: 'a',
+                    modifier: Modifier::ModAlt
+                }),
+                Step::Key(KeyPress {
+                    key: 'b',
+                    modifier: Modifier::ModShift
+                }),
+                Step::Key(KeyPress {
+                    key: '1',
+                    modifier: Modifier::ModAltShift
+                }),
+                Step::Key(key('s')),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_parse_choices_with_ranges() {
+        let steps = parse("[1-3jk]*").unwrap();
+
+        assert_eq!(
+            steps,
+            vec![Step::Choice(vec![
+                key('1'),
+                key('2'),
+                key('3'),
+                key('j'),
+                key('k')
+            ])]
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_notation() {
+        assert!(parse("").is_err());
+        assert!(parse("ab").is_err());
+        assert!(parse("<C-a>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("[12]").is_err());
+        assert!(parse("[11]*").is_err());
+        assert!(parse("[5-1]*").is_err());
+        assert!(parse("[12]* a").is_err());
+        assert!(parse("!").is_err());
+    }
+
+    #[test]
+    fn should_substitute_standalone_ns_only() {
+        assert_eq!(substitute("Toggle(n) on_n n", '3'), "Toggle(3) on_n 3");
+    }
+
+    #[test]
+    fn should_leave_escape_sequences_alone() {
+        assert_eq!(
+            substitute(r#"write(fifo, "%s\n", n)"#, '3'),
+            r#"write(fifo, "%s\n", 3)"#
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Runs a keymap file without writing any code, its actions being the built-ins of
+//! `ActionRegistry::with_builtins`.
+//!
+//! ```bash
+//! keyhook check mixer.keymap            # Reports unknown or malformed actions and conflicts.
+//! keyhook print mixer.keymap [--html]   # Prints a cheat sheet.
+//! keyhook run mixer.keymap [--dry-run] [--journal session.journal]
+//! ```
+//!
+//! `run` hooks the keyboard until a `shutdown` mapping is hit, and fails on platforms without a
+//! backend, which is all but Windows. With `--dry-run` the actions are printed instead of run.
+//! Exits with 0 on success, 1 if `check` finds conflicts and 2 on errors.
+
+use keyboard_hook::cheat_sheet::CheatSheet;
+use keyboard_hook::config::ConfigMappings;
+use keyboard_hook::journal::Journal;
+use keyboard_hook::mapping_trie::MappingTrie;
+use keyboard_hook::types::Event;
+use keyboard_hook::ActionHandler;
+use keyboard_hook::ActionRegistry;
+use keyboard_hook::KeyboardHook;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::mpsc;
+
+const USAGE: &str = "\
+Usage: keyhook check <keymap>
+       keyhook print <keymap> [--html]
+       keyhook run <keymap> [--dry-run] [--journal <path>]";
+
+/// What a subcommand prints, and the code to exit with.
+struct Outcome {
+    output: String,
+    code: u8,
+}
+
+/// Prints the events instead of running their actions.
+struct DryRun;
+
+impl ActionHandler<String, String> for DryRun {
+    fn handle(&self, receiver: mpsc::Receiver<Event<String, String>>) {
+        for event in receiver {
+            println!("would fire: {}", event);
+        }
+    }
+}
+
+fn load(registry: &ActionRegistry, path: &str) -> Result<ConfigMappings, String> {
+    registry
+        .load(path)
+        .map_err(|error| format!("{}: {}", path, error))
+}
+
+fn check(registry: &ActionRegistry, path: &str) -> Result<Outcome, String> {
+    let mappings = load(registry, path)?;
+    let sequences = mappings.len();
+    let (_, conflicts) = MappingTrie::new(&mappings.into());
+
+    if conflicts.is_empty() {
+        return Ok(Outcome {
+            output: format!("{}: {} sequences, no conflicts\n", path, sequences),
+            code: 0,
+        });
+    }
+
+    Ok(Outcome {
+        output: conflicts
+            .iter()
+            .map(|conflict| format!("{}: {}\n", path, conflict))
+            .collect(),
+        code: 1,
+    })
+}
+
+fn print(registry: &ActionRegistry, path: &str, html: bool) -> Result<Outcome, String> {
+    let title = Path::new(path)
+        .file_stem()
+        .map_or(path.into(), |stem| stem.to_string_lossy());
+    let cheat_sheet = CheatSheet::new(&title, &load(registry, path)?.into());
+    let output = if html {
+        cheat_sheet.to_html()
+    } else {
+        cheat_sheet.to_markdown()
+    };
+
+    Ok(Outcome { output, code: 0 })
+}
+
+fn run(
+    registry: ActionRegistry,
+    path: &str,
+    dry_run: bool,
+    journal: Option<&str>,
+) -> Result<Outcome, String> {
+    let mappings = load(&registry, path)?;
+    let handler: Box<dyn ActionHandler<String, String> + Send + Sync> = if dry_run {
+        Box::new(DryRun)
+    } else {
+        Box::new(registry)
+    };
+    let mut hook = KeyboardHook::new(mappings, handler);
+
+    if let Some(journal) = journal {
+        let journal =
+            Journal::create(journal).map_err(|error| format!("{}: {}", journal, error))?;
+        hook = hook.with_journal(journal);
+    }
+
+    hook.hook().map_err(|error| error.to_string())?;
+    Ok(Outcome {
+        output: String::new(),
+        code: 0,
+    })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let registry = ActionRegistry::new().with_builtins();
+
+    let result = match args.as_slice() {
+        ["check", path] => check(&registry, path),
+        ["print", path] => print(&registry, path, false),
+        ["print", path, "--html"] => print(&registry, path, true),
+        ["run", path, options @ ..] => match options {
+            [] => run(registry, path, false, None),
+            ["--dry-run"] => run(registry, path, true, None),
+            ["--journal", journal] => run(registry, path, false, Some(journal)),
+            ["--dry-run", "--journal", journal] | ["--journal", journal, "--dry-run"] => {
+                run(registry, path, true, Some(journal))
+            }
+            _ => Err(USAGE.to_string()),
+        },
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(outcome) => {
+            print!("{}", outcome.output);
+            ExitCode::from(outcome.code)
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes the keymap to a file of its own, removed when dropped.
+    struct Keymap(PathBuf);
+
+    impl Keymap {
+        fn new(name: &str, source: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("{}-{}.keymap", name, std::process::id()));
+            std::fs::write(&path, source).unwrap();
+            Self(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for Keymap {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn should_check_a_keymap() {
+        // Given
+        let registry = ActionRegistry::new().with_builtins();
+        let clean = Keymap::new("clean", r#""<A-a> w" => print(Kenny)"#);
+        let conflicting = Keymap::new(
+            "conflicting",
+            "\"<A-a> w\" => print(Kenny)\n\"<A-a> w\" => print(Stan)",
+        );
+        let malformed = Keymap::new("malformed", r#""<A-a> w" => write(/tmp/keys)"#);
+
+        // When
+        let clean_outcome = check(&registry, clean.path()).unwrap();
+        let conflicting_outcome = check(&registry, conflicting.path()).unwrap();
+        let malformed_error = check(&registry, malformed.path()).err().unwrap();
+
+        // Then
+        assert_eq!(
+            clean_outcome.output,
+            format!("{}: 1 sequences, no conflicts\n", clean.path())
+        );
+        assert_eq!(clean_outcome.code, 0);
+        assert!(conflicting_outcome.output.starts_with(conflicting.path()));
+        assert_eq!(conflicting_outcome.code, 1);
+        assert_eq!(
+            malformed_error,
+            format!(
+                "{}: line 1: `write(/tmp/keys)`: expected `write(path, line)`",
+                malformed.path()
+            )
+        );
+    }
+
+    #[test]
+    fn should_print_a_cheat_sheet() {
+        // Given
+        let registry = ActionRegistry::new().with_builtins();
+        let keymap = Keymap::new("cheats", r#""<A-a> w" => print(Kenny)"#);
+
+        // When
+        let markdown = print(&registry, keymap.path(), false).unwrap();
+        let html = print(&registry, keymap.path(), true).unwrap();
+
+        // Then
+        assert!(markdown.output.contains("print(Kenny)"));
+        assert!(html.output.contains("print(Kenny)"));
+        assert!(html.output.starts_with('<'));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn should_refuse_to_run_without_a_backend() {
+        // Given
+        let registry = ActionRegistry::new().with_builtins();
+        let keymap = Keymap::new("unsupported", r#""<A-a> w" => print(Kenny)"#);
+
+        // When
+        let error = run(registry, keymap.path(), true, None).err().unwrap();
+
+        // Then
+        assert_eq!(error, "Hooking the keyboard is only supported on Windows.");
+    }
+}
@@ -0,0 +1,259 @@
+//! Steps through a keymap file one key press at a time, on a virtual clock, printing what the
+//! engine is waiting for after each step.
+//!
+//! ```text
+//! $ keymap-debug mixer.keymap
+//! > <A-a> s 2
+//! > wait 700ms
+//! ```
+//!
+//! Besides key presses written as in a sequence, it understands `wait <n>ms`, `wait <n>s`,
+//! `stop [flush|discard]`, `reset`, `help` and `quit`.
+
+use keyboard_hook::config;
+use keyboard_hook::engine::Engine;
+use keyboard_hook::mapping_trie::Cursor;
+use keyboard_hook::mapping_trie::MappingTrie;
+use keyboard_hook::types::Event;
+use keyboard_hook::types::FlushPolicy;
+use keyboard_hook::types::KeyPress;
+use std::fmt::Write as _;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::process::ExitCode;
+use std::time::Duration;
+use std::time::Instant;
+
+const HELP: &str = "\
+<A-a> s 2       press keys, written as in a sequence
+wait 700ms      let time pass, firing actions on timeout (also `wait 1s`)
+stop [discard]  stop the engine, flushing actions waiting for a timeout unless discarded
+reset           start over with a fresh engine
+quit            leave";
+
+struct Session {
+    trie: MappingTrie<String, String>,
+    engine: Engine<String, String>,
+    keys: Vec<KeyPress>,
+    now: Instant,
+}
+
+impl Session {
+    fn new(trie: MappingTrie<String, String>) -> Self {
+        Self {
+            engine: Engine::from_trie(trie.clone()),
+            trie,
+            keys: vec![],
+            now: Instant::now(),
+        }
+    }
+
+    fn press(&mut self, key_press: KeyPress) -> String {
+        let (hook_action, events) = self.engine.feed(&key_press, self.now);
+        self.keys.push(key_press.clone());
+
+        format!("{}: {:?}\n{}", key_press, hook_action, self.report(events))
+    }
+
+    fn wait(&mut self, duration: Duration) -> String {
+        self.now += duration;
+        let events = self.engine.tick(self.now);
+
+        format!("waited {:?}\n{}", duration, self.report(events))
+    }
+
+    fn stop(&mut self, policy: FlushPolicy) -> String {
+        let events = self.engine.stop(policy);
+
+        format!("stopped ({:?})\n{}", policy, self.report(events))
+    }
+
+    fn report(&mut self, events: impl IntoIterator<Item = Event<String, String>>) -> String {
+        let events: Vec<_> = events.into_iter().map(|event| event.to_string()).collect();
+        let cursor = self.engine.cursor();
+
+        if cursor == Cursor::root() && self.engine.next_deadline().is_none() {
+            self.keys.clear();
+        }
+
+        let node = self.trie.node(cursor);
+        let keys: Vec<_> = self.keys.iter().map(ToString::to_string).collect();
+        let next: Vec<_> = node
+            .repeatable_keys()
+            .iter()
+            .chain(node.edges().map(|(key, _)| key))
+            .map(ToString::to_string)
+            .collect();
+        let deadline = match self.engine.next_deadline() {
+            Some(deadline) => format!("in {:?}", deadline.saturating_duration_since(self.now)),
+            None => "none".to_string(),
+        };
+        let pending = match self.engine.pending() {
+            Some(event) => event.to_string(),
+            None => "none".to_string(),
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "  keys:     {}", keys.join(" "));
+        let _ = match node.mapping() {
+            Some(mapping) => writeln!(
+                report,
+                "  node:     n{} {:?} {}",
+                node.id(),
+                node.kind(),
+                mapping
+            ),
+            None => writeln!(report, "  node:     n{} {:?}", node.id(), node.kind()),
+        };
+        let _ = writeln!(report, "  next:     {}", next.join(" "));
+        let _ = writeln!(report, "  pending:  {}", pending);
+        let _ = writeln!(report, "  deadline: {}", deadline);
+        let _ = write!(report, "  events:   {}", events.join("; "));
+        report
+    }
+
+    /// Runs a line of input, returning what to print, or none once the session should end.
+    fn run(&mut self, line: &str) -> Result<Option<String>, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let output = match words.as_slice() {
+            [] => String::new(),
+            ["quit"] | ["exit"] => return Ok(None),
+            ["help"] => HELP.to_string(),
+            ["reset"] => {
+                *self = Self::new(self.trie.clone());
+                String::new()
+            }
+            ["wait", duration] => self.wait(parse_duration(duration)?),
+            ["stop"] | ["stop", "flush"] => self.stop(FlushPolicy::Flush),
+            ["stop", "discard"] => self.stop(FlushPolicy::Discard),
+            keys => {
+                let keys = keys
+                    .iter()
+                    .map(|key| key.parse())
+                    .collect::<Result<Vec<KeyPress>, _>>()?;
+
+                keys.into_iter()
+                    .map(|key| self.press(key))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+
+        Ok(Some(output))
+    }
+}
+
+/// Reads `700ms` or `2s`, the digits being everything before the unit.
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let digits = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (amount, unit) = duration.split_at(digits);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("`{}` is not a duration", duration))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" => Ok(Duration::from_secs(amount)),
+        _ => Err(format!("`{}` is not a duration, e.g. 700ms", duration)),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let [keymap] = args.as_slice() else {
+        eprintln!("Usage: keymap-debug <keymap>");
+        return ExitCode::from(2);
+    };
+
+    let mappings = match config::load(keymap) {
+        Ok(mappings) => mappings,
+        Err(error) => {
+            eprintln!("{}: {}", keymap, error);
+            return ExitCode::from(2);
+        }
+    };
+    let (trie, conflicts) = MappingTrie::new(&mappings.into());
+
+    for conflict in conflicts {
+        eprintln!("{}: ignoring {}", keymap, conflict);
+    }
+
+    let mut session = Session::new(trie);
+    let mut lines = io::stdin().lock().lines();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+
+        let Some(Ok(line)) = lines.next() else {
+            return ExitCode::SUCCESS;
+        };
+
+        match session.run(&line) {
+            Ok(Some(output)) if output.is_empty() => {}
+            Ok(Some(output)) => println!("{}", output),
+            Ok(None) => return ExitCode::SUCCESS,
+            Err(error) => println!("{}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let mappings = config::parse(
+            r#"
+            "<A-a> w" => Kenny
+            "<A-a> q" => Princess on timeout
+            "#,
+        )
+        .unwrap();
+
+        Session::new(MappingTrie::new(&mappings.into()).0)
+    }
+
+    #[test]
+    fn should_report_what_the_engine_waits_for() {
+        // Given
+        let mut session = session();
+
+        // When
+        let pressed = session.run("<A-a> q").unwrap().unwrap();
+        let waited = session.run("wait 700ms").unwrap().unwrap();
+
+        // Then
+        assert!(pressed.contains("<A-A>: Suppress"));
+        assert!(pressed.contains("keys:     <A-A> Q\n"));
+        assert!(pressed.contains("pending:  Princess\n"));
+        assert!(pressed.contains("deadline: in 650ms\n"));
+        assert!(waited.contains("keys:     \n"));
+        assert!(waited.ends_with("events:   Princess"));
+    }
+
+    #[test]
+    fn should_end_on_quit_and_reject_bad_input() {
+        // Given
+        let mut session = session();
+
+        // Then
+        assert!(session.run("<C-a>").is_err());
+        assert!(session.run("wait 7m0s").is_err());
+        assert_eq!(session.run("quit"), Ok(None));
+    }
+
+    #[test]
+    fn should_parse_durations() {
+        assert_eq!(parse_duration("700ms"), Ok(Duration::from_millis(700)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert!(parse_duration("7m0s").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("1.5s").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Prints what changed between two keymap files, see `keyboard_hook::config`.
+//!
+//! ```bash
+//! keymap-diff old.keymap new.keymap
+//! ```
+//!
+//! Exits with 0 if the keymaps do the same, 1 if they differ and 2 if one can't be read.
+
+use keyboard_hook::config;
+use keyboard_hook::diff::diff;
+use keyboard_hook::mapping_trie::MappingTrie;
+use std::process::ExitCode;
+
+fn load(path: &str) -> Result<MappingTrie<String, String>, String> {
+    let mappings = config::load(path).map_err(|error| format!("{}: {}", path, error))?;
+    let (trie, conflicts) = MappingTrie::new(&mappings.into());
+
+    for conflict in conflicts {
+        eprintln!("{}: ignoring {}", path, conflict);
+    }
+
+    Ok(trie)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let [before, after] = args.as_slice() else {
+        eprintln!("Usage: keymap-diff <before> <after>");
+        return ExitCode::from(2);
+    };
+
+    let (before, after) = match (load(before), load(after)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("{}", error);
+            return ExitCode::from(2);
+        }
+    };
+
+    let changes = diff(&before, &after);
+
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    ExitCode::from(u8::from(!changes.is_empty()))
+}
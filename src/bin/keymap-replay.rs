@@ -0,0 +1,52 @@
+//! Replays a journal recorded with `KeyboardHook::with_journal` against a keymap file, see
+//! `keyboard_hook::journal`.
+//!
+//! ```bash
+//! keymap-replay mixer.keymap session.journal
+//! ```
+//!
+//! Exits with 0 if the replay did what was recorded, 1 if it diverged and 2 if a file can't be
+//! read.
+
+use keyboard_hook::config;
+use keyboard_hook::engine::Engine;
+use keyboard_hook::journal::replay;
+use std::process::ExitCode;
+
+fn run(keymap: &str, journal: &str) -> Result<bool, String> {
+    let mappings = config::load(keymap).map_err(|error| format!("{}: {}", keymap, error))?;
+    let (engine, conflicts) = Engine::new(mappings);
+
+    for conflict in conflicts {
+        eprintln!("{}: ignoring {}", keymap, conflict);
+    }
+
+    let recorded =
+        std::fs::read_to_string(journal).map_err(|error| format!("{}: {}", journal, error))?;
+    let divergences =
+        replay(&recorded, engine).map_err(|error| format!("{}: {}", journal, error))?;
+
+    for divergence in &divergences {
+        println!("{}", divergence);
+    }
+
+    Ok(divergences.is_empty())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let [keymap, journal] = args.as_slice() else {
+        eprintln!("Usage: keymap-replay <keymap> <journal>");
+        return ExitCode::from(2);
+    };
+
+    match run(keymap, journal) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use crate::types::Behaviour;
+use crate::types::Behaviours;
+use crate::types::Conflict;
+use crate::types::Key;
+use crate::types::KeyPress;
+use crate::types::Mapping;
+use crate::types::Modifier::*;
+use std::fmt::Debug;
+use std::fmt::Display;
+
+pub fn alt(key: Key) -> KeyPress {
+    KeyPress::Mod(key, ModAlt)
+}
+
+pub fn shift(key: Key) -> KeyPress {
+    KeyPress::Mod(key, ModShift)
+}
+
+pub fn alt_shift(key: Key) -> KeyPress {
+    KeyPress::Mod(key, ModAltShift)
+}
+
+struct Node<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    behaviour: Behaviour<A>,
+    next: Children<A, T>,
+}
+
+struct Children<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    nodes: Vec<Node<A, T>>,
+    choices: Vec<(Behaviours<A>, T)>,
+}
+
+impl<A, T> Children<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn new() -> Self {
+        Self {
+            nodes: vec![],
+            choices: vec![],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.choices.is_empty()
+    }
+
+    fn in_choice(&self, key: &KeyPress) -> bool {
+        self.choices
+            .iter()
+            .any(|(behaviours, _)| behaviours.get_mapping(key).is_some())
+    }
+
+    fn flatten(&self, path: &mut Vec<Mapping<A, T>>, result: &mut Vec<Vec<Mapping<A, T>>>) {
+        for node in &self.nodes {
+            path.push(Mapping::Single(node.behaviour.clone()));
+
+            if node.next.is_empty() {
+                result.push(path.clone());
+            } else {
+                node.next.flatten(path, result);
+            }
+
+            path.pop();
+        }
+
+        for (behaviours, tag) in &self.choices {
+            let mut sequence = path.clone();
+            sequence.push(Mapping::Choice(behaviours.clone(), tag.clone()));
+            result.push(sequence);
+        }
+    }
+}
+
+/// Builds mappings programmatically. Sequences sharing a prefix share its keys, so a key mapped to
+/// an action in one sequence keeps that action when another sequence passes through it.
+///
+/// ```ignore
+/// let mappings = Keymap::new()
+///     .seq(alt(KeyA))
+///     .then(KeyS)
+///     .then_aot(Key2, UseStrip2)
+///     .repeat_choice(ToggleChannels, |c| {
+///         (1..=5).fold(c, |c, n| c.key_aot(Key::from_u8(b'0' + n), ToggleChannel(n)))
+///     })
+///     .seq(alt(KeyA))
+///     .then_a(KeyW, Kenny)
+///     .build()?;
+/// ```
+pub struct Keymap<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    root: Children<A, T>,
+    conflicts: Vec<Conflict>,
+}
+
+impl<A, T> Default for Keymap<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, T> Keymap<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    pub fn new() -> Self {
+        Self {
+            root: Children::new(),
+            conflicts: vec![],
+        }
+    }
+
+    /// Starts an empty sequence, e.g. for a single key mapped to an action.
+    pub fn begin(self) -> Sequence<A, T> {
+        Sequence {
+            keymap: self,
+            path: vec![],
+        }
+    }
+
+    /// Starts a sequence with a key that triggers a timeout.
+    pub fn seq(self, key: impl Into<KeyPress>) -> Sequence<A, T> {
+        self.begin().then(key)
+    }
+
+    pub fn build(self) -> Result<Vec<Vec<Mapping<A, T>>>, Vec<Conflict>> {
+        if !self.conflicts.is_empty() {
+            return Err(self.conflicts);
+        }
+
+        let mut result = vec![];
+        self.root.flatten(&mut vec![], &mut result);
+
+        Ok(result)
+    }
+
+    fn children(&mut self, path: &[KeyPress]) -> &mut Children<A, T> {
+        let mut children = &mut self.root;
+
+        for key in path {
+            children = &mut children
+                .nodes
+                .iter_mut()
+                .find(|node| node.behaviour.get_key() == *key)
+                .expect("Sequence path not found in the keymap.")
+                .next;
+        }
+
+        children
+    }
+
+    fn conflict(&mut self, path: &[KeyPress], key: Option<&KeyPress>, reason: String) {
+        let mut path = path.to_vec();
+        path.extend(key.cloned());
+        self.conflicts.push(Conflict {
+            path,
+            reason,
+            mount: None,
+        });
+    }
+
+    /// Adds a key under the given path, returning false if it conflicts with existing mappings.
+    fn add(&mut self, path: &[KeyPress], behaviour: Behaviour<A>) -> bool {
+        let key = behaviour.get_key();
+        let children = self.children(path);
+
+        if children.in_choice(&key) {
+            self.conflict(
+                path,
+                Some(&key),
+                "the key is already part of a choice".to_string(),
+            );
+            return false;
+        }
+
+        match children
+            .nodes
+            .iter_mut()
+            .find(|node| node.behaviour.get_key() == key)
+        {
+            None => {
+                children.nodes.push(Node {
+                    behaviour,
+                    next: Children::new(),
+                });
+                true
+            }
+            Some(node) => match (&node.behaviour, &behaviour) {
+                (existing, new) if existing == new => true,
+                (_, Behaviour::Timeout(_)) => true,
+                (Behaviour::Timeout(_), Behaviour::Action(..) | Behaviour::Shutdown(_))
+                    if !node.next.is_empty() =>
+                {
+                    let reason = "the key leads to other sequences".to_string();
+                    self.conflict(path, Some(&key), reason);
+                    false
+                }
+                (Behaviour::Timeout(_), _) => {
+                    node.behaviour = behaviour;
+                    true
+                }
+                (existing, _) => {
+                    let reason = format!("the key is already mapped to {}", existing);
+                    self.conflict(path, Some(&key), reason);
+                    false
+                }
+            },
+        }
+    }
+
+    fn add_choice(&mut self, path: &[KeyPress], behaviours: Behaviours<A>, tag: T) {
+        let children = self.children(path);
+        let taken = behaviours.0.iter().map(|b| b.get_key()).find(|key| {
+            children.in_choice(key)
+                || children
+                    .nodes
+                    .iter()
+                    .any(|node| node.behaviour.get_key() == *key)
+        });
+
+        match taken {
+            Some(key) => self.conflict(
+                path,
+                Some(&key),
+                format!("the key of choice ({}) is already mapped", tag),
+            ),
+            None => children.choices.push((behaviours, tag)),
+        }
+    }
+}
+
+/// A sequence being built. Steps are added to the keymap as they're defined.
+pub struct Sequence<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    keymap: Keymap<A, T>,
+    path: Vec<KeyPress>,
+}
+
+impl<A, T> Sequence<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn step(mut self, behaviour: Behaviour<A>) -> Self {
+        if let Some(Behaviour::Action(..) | Behaviour::Shutdown(_)) = self.last_behaviour() {
+            let key = behaviour.get_key();
+            let reason = "the sequence has already fired".to_string();
+            self.keymap.conflict(&self.path, Some(&key), reason);
+            return self;
+        }
+
+        let key = behaviour.get_key();
+
+        if self.keymap.add(&self.path, behaviour) {
+            self.path.push(key);
+        }
+
+        self
+    }
+
+    fn last_behaviour(&mut self) -> Option<Behaviour<A>> {
+        let (key, parent) = self.path.split_last()?;
+
+        self.keymap
+            .children(parent)
+            .nodes
+            .iter()
+            .find(|node| node.behaviour.get_key() == *key)
+            .map(|node| node.behaviour.clone())
+    }
+
+    /// Adds a key that triggers a timeout.
+    pub fn then(self, key: impl Into<KeyPress>) -> Self {
+        self.step(Behaviour::Timeout(key.into()))
+    }
+
+    /// Adds a key whose action fires on timeout, unless the sequence continues.
+    pub fn then_aot(self, key: impl Into<KeyPress>, action: A) -> Self {
+        self.step(Behaviour::ActionOnTimeout(key.into(), action))
+    }
+
+    /// Ends the sequence with a key whose action fires immediately.
+    pub fn then_a(self, key: impl Into<KeyPress>, action: A) -> Keymap<A, T> {
+        self.step(Behaviour::Action(key.into(), action)).end()
+    }
+
+    /// Ends the sequence with a key that unhooks the keyboard.
+    pub fn then_shutdown(self, key: impl Into<KeyPress>) -> Keymap<A, T> {
+        self.step(Behaviour::Shutdown(key.into())).end()
+    }
+
+    /// Ends the sequence with a repeatable choice of keys.
+    pub fn repeat_choice(
+        mut self,
+        tag: T,
+        choice: impl FnOnce(Choice<A>) -> Choice<A>,
+    ) -> Keymap<A, T> {
+        let behaviours = choice(Choice { behaviours: vec![] }).behaviours;
+        self.keymap
+            .add_choice(&self.path, Behaviours(behaviours), tag);
+        self.end()
+    }
+
+    /// Continues the sequence in another way, keeping this one at the current key. Handy for
+    /// reusing the same sub-tree under different prefixes:
+    ///
+    /// ```ignore
+    /// fn volume(s: Sequence<MyActions, MyTags>) -> Keymap<MyActions, MyTags> {
+    ///     s.repeat_choice(Volume, |c| c.key_a(KeyJ, VolumeDown).key_a(KeyK, VolumeUp))
+    /// }
+    ///
+    /// Keymap::new().seq(alt(KeyA)).branch(volume).then(KeyS).branch(volume).end()
+    /// ```
+    pub fn branch(self, branch: impl FnOnce(Sequence<A, T>) -> Keymap<A, T>) -> Self {
+        let path = self.path.clone();
+        let keymap = branch(self);
+
+        Sequence { keymap, path }
+    }
+
+    /// Ends the sequence.
+    pub fn end(self) -> Keymap<A, T> {
+        self.keymap
+    }
+}
+
+/// The keys of a repeatable choice.
+pub struct Choice<A>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    behaviours: Vec<Behaviour<A>>,
+}
+
+impl<A> Choice<A>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    /// A key that only extends the timeout.
+    pub fn key(mut self, key: impl Into<KeyPress>) -> Self {
+        self.behaviours.push(Behaviour::Timeout(key.into()));
+        self
+    }
+
+    /// A key whose action fires immediately.
+    pub fn key_a(mut self, key: impl Into<KeyPress>, action: A) -> Self {
+        self.behaviours.push(Behaviour::Action(key.into(), action));
+        self
+    }
+
+    /// A key whose action is aggregated with the rest and fired on timeout.
+    pub fn key_aot(mut self, key: impl Into<KeyPress>, action: A) -> Self {
+        self.behaviours
+            .push(Behaviour::ActionOnTimeout(key.into(), action));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::types::Key::*;
+
+    fn channels(s: Sequence<String, String>) -> Keymap<String, String> {
+        s.repeat_choice("Channels".to_string(), |c| {
+            (1..=3).fold(c, |c, n| {
+                c.key_aot(Key::from_u8(b'0' + n), format!("Channel{}", n))
+            })
+        })
+    }
+
+    #[test]
+    fn should_build_the_same_mappings_as_the_keymap_macro() {
+        let built = Keymap::new()
+            .seq(alt(KeyA))
+            .then(KeyE)
+            .then(KeyX)
+            .then_shutdown(KeyT)
+            .seq(alt(KeyA))
+            .then_a(KeyW, "Kenny".to_string())
+            .seq(alt(KeyA))
+            .branch(channels)
+            .then(KeyS)
+            .then_aot(Key2, "UseStrip2".to_string())
+            .branch(channels)
+            .end()
+            .build()
+            .unwrap();
+
+        let expected: Vec<Vec<Mapping<String, String>>> = keymap! {
+            "<A-a> e x t" => shutdown,
+            "<A-a> w" => "Kenny".to_string(),
+            "<A-a> [1-3]*" as "Channels".to_string() => format!("Channel{}", n) on timeout,
+            "<A-a> s 2" => "UseStrip2".to_string() on timeout,
+            "<A-a> s 2 [1-3]*" as "Channels".to_string() => format!("Channel{}", n) on timeout,
+        };
+
+        // The builder leaves out "<A-a> s 2", as it's a prefix of another sequence.
+        assert_eq!(built.len(), expected.len() - 1);
+        assert!(built.iter().all(|sequence| expected.contains(sequence)));
+    }
+
+    #[test]
+    fn should_share_actions_of_common_prefixes() {
+        let built: Vec<Vec<Mapping<&str, &str>>> = Keymap::new()
+            .seq(KeyA)
+            .then(KeyB)
+            .then_a(KeyC, "C")
+            .seq(KeyA)
+            .then_aot(KeyB, "B")
+            .end()
+            .build()
+            .unwrap();
+
+        let expected: Vec<Vec<Mapping<&str, &str>>> = keymap! {
+            "a b" => "B" on timeout,
+            "a b c" => "C",
+        };
+
+        assert_eq!(built, expected[1..]);
+    }
+
+    #[test]
+    fn should_report_conflicts() {
+        let conflicts = Keymap::<&str, &str>::new()
+            .seq(KeyA)
+            .then_a(KeyB, "B")
+            .seq(KeyA)
+            .then_a(KeyB, "C")
+            .seq(KeyA)
+            .repeat_choice("Tag", |c| c.key_aot(KeyB, "D"))
+            .build()
+            .unwrap_err();
+
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].path, vec![KeyA.into(), KeyB.into()]);
+    }
+
+    #[test]
+    fn should_report_an_action_on_a_key_that_leads_further() {
+        let conflicts = Keymap::<&str, &str>::new()
+            .seq(KeyA)
+            .then(KeyB)
+            .then_a(KeyC, "C")
+            .seq(KeyA)
+            .then_a(KeyB, "B")
+            .build()
+            .unwrap_err();
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, vec![KeyA.into(), KeyB.into()]);
+    }
+}
@@ -0,0 +1,306 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// The actions an [`crate::ActionRegistry`] knows with `with_builtins`, written in a keymap as
+/// `run(mixer toggle {actions}, MIXER=main)`, `spawn(notepad.exe)`, `write(/tmp/keys, {actions})`
+/// or `print(volume up)`. `{actions}` and `{keys}` stand for the actions of a choice and the keys
+/// picking them, separated by spaces, when the built-in is the choice's tag.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Builtin {
+    /// Runs a command line with the shell, with extra environment variables, and reports how it
+    /// ended. It's waited for on a thread of its own and killed after [`RUN_TIMEOUT`].
+    Run {
+        command: String,
+        env: Vec<(String, String)>,
+    },
+    /// Starts a command line with the shell without waiting for it.
+    Spawn {
+        command: String,
+        env: Vec<(String, String)>,
+    },
+    /// Appends a line to a file or FIFO, which blocks until the FIFO has a reader.
+    Write { path: String, line: String },
+    /// Prints a line on stdout.
+    Print(String),
+}
+
+pub const NAMES: [&str; 4] = ["run", "spawn", "write", "print"];
+
+pub const RUN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Splits on commas outside of quotes. Quotes wrapping a whole argument are removed, the ones
+/// within it are kept for the shell.
+fn arguments(text: &str) -> Vec<String> {
+    let mut arguments = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            ',' if !quoted => arguments.push(std::mem::take(&mut current)),
+            c => {
+                quoted ^= c == '"';
+                current.push(c);
+            }
+        }
+    }
+
+    arguments.push(current);
+    arguments
+        .iter()
+        .map(|argument| {
+            let argument = argument.trim();
+
+            match argument.strip_prefix('"').and_then(|a| a.strip_suffix('"')) {
+                Some(unquoted) if !unquoted.contains('"') => unquoted.to_string(),
+                _ => argument.to_string(),
+            }
+        })
+        .collect()
+}
+
+fn command_and_env(arguments: Vec<String>) -> Result<(String, Vec<(String, String)>), String> {
+    let mut arguments = arguments.into_iter();
+    let command = arguments.next().filter(|command| !command.is_empty());
+    let command = command.ok_or("expected a command line")?;
+    let env = arguments
+        .map(|variable| match variable.split_once('=') {
+            Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+            _ => Err(format!("expected `NAME=value`, found `{}`", variable)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((command, env))
+}
+
+impl Builtin {
+    /// Parses the built-in from its name and what's between the parentheses.
+    pub fn parse(name: &str, argument: &str) -> Result<Self, String> {
+        let arguments = arguments(argument);
+
+        match name {
+            "run" => command_and_env(arguments).map(|(command, env)| Builtin::Run { command, env }),
+            "spawn" => {
+                command_and_env(arguments).map(|(command, env)| Builtin::Spawn { command, env })
+            }
+            "write" => match arguments.as_slice() {
+                [path, line] if !path.is_empty() => Ok(Builtin::Write {
+                    path: path.clone(),
+                    line: line.clone(),
+                }),
+                _ => Err("expected `write(path, line)`".to_string()),
+            },
+            "print" => Ok(Builtin::Print(arguments.join(", "))),
+            _ => Err(format!("`{}` is not a built-in action", name)),
+        }
+    }
+
+    /// Replaces `{actions}` with the actions and `{keys}` with the keys, separated by spaces.
+    pub fn interpolate(self, actions: &[String], keys: &[String]) -> Self {
+        let actions = actions.join(" ");
+        let keys = keys.join(" ");
+        let fill = |text: String| text.replace("{actions}", &actions).replace("{keys}", &keys);
+
+        match self {
+            Builtin::Run { command, env } => Builtin::Run {
+                command: fill(command),
+                env,
+            },
+            Builtin::Spawn { command, env } => Builtin::Spawn {
+                command: fill(command),
+                env,
+            },
+            Builtin::Write { path, line } => Builtin::Write {
+                path,
+                line: fill(line),
+            },
+            Builtin::Print(line) => Builtin::Print(fill(line)),
+        }
+    }
+
+    fn shell(command: &str, env: &[(String, String)]) -> Command {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+
+        shell.arg(command).envs(env.iter().cloned());
+        shell
+    }
+
+    /// Waits for the command, killing it once it runs for longer than the timeout.
+    fn supervise(command: String, mut child: Child, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    if !status.success() {
+                        tracing::warn!(command, %status, "Command failed");
+                    }
+
+                    return;
+                }
+                Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                Ok(None) => {
+                    tracing::warn!(command, ?timeout, "Command timed out");
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                Err(error) => {
+                    tracing::warn!(command, %error, "Command lost");
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn execute(&self) -> io::Result<()> {
+        match self {
+            Builtin::Run { command, env } => {
+                let child = Self::shell(command, env).stdin(Stdio::null()).spawn()?;
+                let command = command.clone();
+
+                thread::spawn(move || Self::supervise(command, child, RUN_TIMEOUT));
+            }
+            Builtin::Spawn { command, env } => {
+                let mut shell = Self::shell(command, env);
+                shell
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+
+                #[cfg(windows)]
+                {
+                    use std::os::windows::process::CommandExt;
+
+                    const DETACHED_PROCESS: u32 = 0x0000_0008;
+                    shell.creation_flags(DETACHED_PROCESS);
+                }
+
+                shell.spawn()?;
+            }
+            Builtin::Write { path, line } => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                writeln!(file, "{}", line)?;
+            }
+            Builtin::Print(line) => println!("{}", line),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_and_interpolate_builtins() {
+        // When
+        let run = Builtin::parse("run", r#""mixer toggle {actions}", MIXER=main"#).unwrap();
+        let write = Builtin::parse("write", "/tmp/keys, {actions}").unwrap();
+
+        // Then
+        assert_eq!(
+            run.interpolate(&["1".to_string(), "3".to_string()], &[]),
+            Builtin::Run {
+                command: "mixer toggle 1 3".to_string(),
+                env: vec![("MIXER".to_string(), "main".to_string())],
+            }
+        );
+        assert_eq!(
+            write,
+            Builtin::Write {
+                path: "/tmp/keys".to_string(),
+                line: "{actions}".to_string(),
+            }
+        );
+        assert!(Builtin::parse("run", "").is_err());
+        assert!(Builtin::parse("write", "/tmp/keys").is_err());
+    }
+
+    #[test]
+    fn should_keep_quotes_within_arguments() {
+        // When
+        let run = Builtin::parse("run", r#"grep "a, b" file, "LANG=C""#).unwrap();
+        let write = Builtin::parse("write", r#"/tmp/fifo, "%s\n""#).unwrap();
+
+        // Then
+        assert_eq!(
+            run,
+            Builtin::Run {
+                command: r#"grep "a, b" file"#.to_string(),
+                env: vec![("LANG".to_string(), "C".to_string())],
+            }
+        );
+        assert_eq!(
+            write,
+            Builtin::Write {
+                path: "/tmp/fifo".to_string(),
+                line: r"%s\n".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn should_interpolate_keys() {
+        // Given
+        let print = Builtin::parse("print", "toggle {actions} with {keys}").unwrap();
+
+        // When
+        let print = print.interpolate(
+            &["Toggle(1)".to_string(), "Toggle(3)".to_string()],
+            &["1".to_string(), "3".to_string()],
+        );
+
+        // Then
+        assert_eq!(
+            print,
+            Builtin::Print("toggle Toggle(1) Toggle(3) with 1 3".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_not_wait_for_commands_to_finish() {
+        // Given
+        let run = Builtin::parse("run", "sleep 5").unwrap();
+        let start = Instant::now();
+
+        // When
+        run.execute().unwrap();
+
+        // Then
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_kill_commands_running_too_long() {
+        // Given
+        let child = Builtin::shell("sleep 5", &[]).spawn().unwrap();
+        let id = child.id();
+        let start = Instant::now();
+
+        // When
+        Builtin::supervise("sleep 5".to_string(), child, Duration::from_millis(50));
+
+        // Then
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!std::path::Path::new(&format!("/proc/{}", id)).exists());
+    }
+}
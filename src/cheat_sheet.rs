@@ -0,0 +1,348 @@
+use crate::fragment::Mappings;
+use crate::types::format_keys;
+use crate::types::Behaviour;
+use crate::types::Mapping;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Write;
+
+/// When an action fires once its key is pressed.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Firing {
+    Immediately,
+    OnTimeout,
+}
+
+/// A key and the action it triggers.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Entry {
+    pub key: String,
+    pub action: String,
+    pub firing: Firing,
+    /// Whether the key belongs to a choice and can be pressed again and again.
+    pub repeatable: bool,
+}
+
+impl Entry {
+    fn when(&self) -> String {
+        let firing = match self.firing {
+            Firing::Immediately => "immediately",
+            Firing::OnTimeout => "on timeout",
+        };
+
+        if self.repeatable {
+            format!("{}, repeatable", firing)
+        } else {
+            firing.to_string()
+        }
+    }
+}
+
+/// Entries sharing the keys pressed before them, and the tag of their choice if any.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Group {
+    pub prefix: String,
+    pub tag: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+impl Group {
+    fn heading(&self) -> String {
+        let prefix = if self.prefix.is_empty() {
+            "(no prefix)"
+        } else {
+            &self.prefix
+        };
+
+        match &self.tag {
+            Some(tag) if !tag.is_empty() => format!("{} — {}", prefix, tag),
+            _ => prefix.to_string(),
+        }
+    }
+}
+
+/// A summary of the mappings for people, grouped by prefix and choice, in keymap order.
+///
+/// ```ignore
+/// let sheet = CheatSheet::new("Mixer", &mappings);
+/// std::fs::write("keymap.md", sheet.to_markdown())?;
+/// std::fs::write("keymap.html", sheet.to_html())?;
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct CheatSheet {
+    pub title: String,
+    pub groups: Vec<Group>,
+}
+
+impl CheatSheet {
+    /// Walks the sequences, including the ones of mounted fragments.
+    pub fn new<A, T>(title: &str, mappings: &Mappings<A, T>) -> Self
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    {
+        let mut sheet = Self {
+            title: title.to_string(),
+            groups: vec![],
+        };
+
+        for sequence in &mappings.sequences {
+            sheet.add(sequence);
+        }
+
+        for (path, fragment) in &mappings.mounts {
+            let prefix = mappings.prefix(path);
+
+            for sequence in fragment.sequences() {
+                sheet.add(&[prefix.clone(), sequence.clone()].concat());
+            }
+        }
+
+        sheet
+    }
+
+    fn group(&mut self, prefix: &[String], tag: Option<String>) -> &mut Group {
+        let prefix = prefix.join(" ");
+        let position = self
+            .groups
+            .iter()
+            .position(|group| group.prefix == prefix && group.tag == tag);
+
+        let index = match position {
+            Some(index) => index,
+            None => {
+                self.groups.push(Group {
+                    prefix,
+                    tag,
+                    entries: vec![],
+                });
+                self.groups.len() - 1
+            }
+        };
+
+        &mut self.groups[index]
+    }
+
+    /// Adds an entry for every key of the sequence that does more than wait for the next one,
+    /// including keys leading further, as another sequence may not end there.
+    fn add<A, T>(&mut self, sequence: &[Mapping<A, T>])
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    {
+        let mut path: Vec<String> = vec![];
+
+        for mapping in sequence {
+            match mapping {
+                Mapping::Single(behaviour) => {
+                    if let Some(entry) = entry(behaviour, false) {
+                        let group = self.group(&path, None);
+
+                        if !group.entries.contains(&entry) {
+                            group.entries.push(entry);
+                        }
+                    }
+
+                    path.push(behaviour.get_key().to_string());
+                }
+                Mapping::Choice(behaviours, tag) => {
+                    let group = self.group(&path, Some(tag.to_string()));
+
+                    for entry in behaviours.0.iter().filter_map(|b| entry(b, true)) {
+                        if !group.entries.contains(&entry) {
+                            group.entries.push(entry);
+                        }
+                    }
+
+                    let keys: Vec<_> = behaviours.0.iter().map(|b| b.get_key()).collect();
+                    path.push(format!("[{}]*", format_keys(&keys)));
+                }
+            }
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title);
+
+        for group in &self.groups {
+            let _ = write!(
+                out,
+                "\n## {}\n\n| Key | Action | When |\n| --- | --- | --- |\n",
+                escape_markdown(&group.heading())
+            );
+
+            for entry in &group.entries {
+                let _ = writeln!(
+                    out,
+                    "| `{}` | {} | {} |",
+                    entry.key.replace('`', "\\`"),
+                    escape_markdown(&entry.action),
+                    entry.when()
+                );
+            }
+        }
+
+        out
+    }
+
+    /// A standalone page with its own styles.
+    pub fn to_html(&self) -> String {
+        let title = escape_html(&self.title);
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n\
+             body {{ font-family: sans-serif; margin: 2em; }}\n\
+             table {{ border-collapse: collapse; margin-bottom: 1.5em; }}\n\
+             th, td {{ border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }}\n\
+             kbd {{ border: 1px solid #999; border-radius: 3px; padding: 0 0.3em; }}\n\
+             </style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, title
+        );
+
+        for group in &self.groups {
+            let _ = write!(
+                out,
+                "<h2>{}</h2>\n<table>\n<tr><th>Key</th><th>Action</th><th>When</th></tr>\n",
+                escape_html(&group.heading())
+            );
+
+            for entry in &group.entries {
+                let _ = writeln!(
+                    out,
+                    "<tr><td><kbd>{}</kbd></td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&entry.key),
+                    escape_html(&entry.action),
+                    entry.when()
+                );
+            }
+
+            out.push_str("</table>\n");
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+/// The entry of a key, unless it only waits for the next key of a sequence.
+fn entry<A>(behaviour: &Behaviour<A>, repeatable: bool) -> Option<Entry>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    let (action, firing) = match behaviour {
+        Behaviour::Timeout(_) => return None,
+        Behaviour::Action(_, action) => (action.to_string(), Firing::Immediately),
+        Behaviour::ActionOnTimeout(_, action) => (action.to_string(), Firing::OnTimeout),
+        Behaviour::Shutdown(_) => ("Shut down".to_string(), Firing::Immediately),
+    };
+
+    Some(Entry {
+        key: behaviour.get_key().to_string(),
+        action,
+        firing,
+        repeatable,
+    })
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::alt;
+    use crate::builder::Keymap;
+    use crate::keymap;
+    use crate::types::Key::*;
+
+    fn sheet() -> CheatSheet {
+        let mappings = keymap! {
+            "<A-a> e x i t" => shutdown,
+            "<A-a> w" => "Kenny",
+            "<A-a> q" => "Princess" on timeout,
+            "<A-a> [jk]*" as "Volume" => { j => "VolumeDown", k => "VolumeUp" },
+        };
+
+        CheatSheet::new("Mixer", &mappings.into())
+    }
+
+    #[test]
+    fn should_group_by_prefix_and_choice() {
+        let sheet = sheet();
+
+        let headings: Vec<_> = sheet.groups.iter().map(Group::heading).collect();
+        assert_eq!(headings, ["<A-A> E X I", "<A-A>", "<A-A> — Volume"]);
+        assert_eq!(
+            sheet.groups[2].entries[1],
+            Entry {
+                key: "K".to_string(),
+                action: "VolumeUp".to_string(),
+                firing: Firing::Immediately,
+                repeatable: true,
+            }
+        );
+    }
+
+    #[test]
+    fn should_list_actions_of_keys_leading_further() {
+        // Given
+        let mappings: Vec<Vec<Mapping<&str, &str>>> = Keymap::new()
+            .seq(alt(KeyA))
+            .then(KeyS)
+            .then_aot(Key2, "UseStrip2")
+            .repeat_choice("Channels", |c| c.key_aot(Key1, "Toggle1"))
+            .build()
+            .unwrap();
+
+        // When
+        let sheet = CheatSheet::new("Mixer", &mappings.into());
+
+        // Then
+        let headings: Vec<_> = sheet.groups.iter().map(Group::heading).collect();
+        assert_eq!(headings, ["<A-A> S", "<A-A> S 2 — Channels"]);
+        assert_eq!(
+            sheet.groups[0].entries,
+            [Entry {
+                key: "2".to_string(),
+                action: "UseStrip2".to_string(),
+                firing: Firing::OnTimeout,
+                repeatable: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn should_write_markdown() {
+        assert_eq!(
+            sheet().to_markdown(),
+            "# Mixer\n\
+             \n## <A-A> E X I\n\n| Key | Action | When |\n| --- | --- | --- |\n\
+             | `T` | Shut down | immediately |\n\
+             \n## <A-A>\n\n| Key | Action | When |\n| --- | --- | --- |\n\
+             | `W` | Kenny | immediately |\n\
+             | `Q` | Princess | on timeout |\n\
+             \n## <A-A> — Volume\n\n| Key | Action | When |\n| --- | --- | --- |\n\
+             | `J` | VolumeDown | immediately, repeatable |\n\
+             | `K` | VolumeUp | immediately, repeatable |\n"
+        );
+    }
+
+    #[test]
+    fn should_escape_keys_in_html() {
+        let html = sheet().to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>&lt;A-A&gt; — Volume</h2>"));
+        assert!(html.contains(
+            "<tr><td><kbd>K</kbd></td><td>VolumeUp</td><td>immediately, repeatable</td></tr>"
+        ));
+    }
+}
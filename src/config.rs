@@ -0,0 +1,455 @@
+use crate::error::ConfigError;
+use crate::types::Behaviour;
+use crate::types::Behaviours;
+use crate::types::KeyPress;
+use crate::types::Mapping;
+use keyboard_hook_notation::Step;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Mappings read from a keymap file, with actions and tags kept as written.
+pub type ConfigMappings = Vec<Vec<Mapping<String, String>>>;
+
+#[derive(Clone)]
+enum Spec {
+    Timeout,
+    Shutdown,
+    Action(String),
+    ActionOnTimeout(String),
+}
+
+impl Spec {
+    fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+
+        match text {
+            "" => Err("expected a behaviour after `=>`".to_string()),
+            "timeout" => Ok(Spec::Timeout),
+            "shutdown" => Ok(Spec::Shutdown),
+            _ => match text.strip_suffix("on timeout") {
+                Some(action) if action.ends_with(char::is_whitespace) => {
+                    Ok(Spec::ActionOnTimeout(unquote(action)))
+                }
+                _ => Ok(Spec::Action(unquote(text))),
+            },
+        }
+    }
+
+    /// Replaces every `n` in the action with the key of the choice.
+    fn instantiate(&self, key: char) -> Spec {
+        match self {
+            Spec::Action(action) => Spec::Action(keyboard_hook_notation::substitute(action, key)),
+            Spec::ActionOnTimeout(action) => {
+                Spec::ActionOnTimeout(keyboard_hook_notation::substitute(action, key))
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn behaviour(self, key: KeyPress) -> Behaviour<String> {
+        match self {
+            Spec::Timeout => Behaviour::Timeout(key),
+            Spec::Shutdown => Behaviour::Shutdown(key),
+            Spec::Action(action) => Behaviour::Action(key, action),
+            Spec::ActionOnTimeout(action) => Behaviour::ActionOnTimeout(key, action),
+        }
+    }
+}
+
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+        .to_string()
+}
+
+/// Whether a `{` outside of quotes is still waiting for its `}`.
+fn is_block_open(entry: &str) -> bool {
+    let mut depth = 0;
+    let mut quoted = false;
+
+    for c in entry.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '{' if !quoted => depth += 1,
+            '}' if !quoted => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth > 0
+}
+
+/// Splits on commas outside of parentheses and quotes.
+fn split_entries(text: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&text[start..]);
+    parts
+        .into_iter()
+        .filter(|part| !part.trim().is_empty())
+        .collect()
+}
+
+fn parse_entry(text: &str) -> Result<Vec<Mapping<String, String>>, String> {
+    let Some((notation, rest)) = text.strip_prefix('"').and_then(|text| text.split_once('"'))
+    else {
+        return Err("an entry starts with a quoted sequence, e.g. `\"<A-a> w\" => Action`".into());
+    };
+
+    let mut steps = keyboard_hook_notation::parse(notation)?;
+    let Some((head, spec)) = rest.split_once("=>") else {
+        return Err(format!("expected `=>` after `\"{}\"`", notation));
+    };
+
+    let tag = match head.trim() {
+        "" => None,
+        head => match head.strip_prefix("as") {
+            Some(tag) if tag.starts_with(char::is_whitespace) => Some(unquote(tag)),
+            _ => return Err(format!("expected `as Tag` or `=>`, found `{}`", head)),
+        },
+    };
+
+    let last = steps.pop();
+    let mut sequence: Vec<Mapping<String, String>> = steps
+        .into_iter()
+        .filter_map(|step| match step {
+            Step::Key(key) => Some(Mapping::Single(Behaviour::Timeout(key.into()))),
+            Step::Choice(_) => None,
+        })
+        .collect();
+
+    let mapping = match (last, tag) {
+        (Some(Step::Key(key)), None) => Mapping::Single(Spec::parse(spec)?.behaviour(key.into())),
+        (Some(Step::Key(_)), Some(_)) => return Err("only choices take a tag".to_string()),
+        (Some(Step::Choice(_)), None) => {
+            return Err(format!(
+                "a choice needs a tag, e.g. `\"{}\" as Tag => ...`",
+                notation
+            ))
+        }
+        (Some(Step::Choice(keys)), Some(tag)) => {
+            let behaviours = choice_behaviours(spec, &keys, notation)?;
+            Mapping::Choice(Behaviours(behaviours), tag)
+        }
+        (None, _) => unreachable!("A parsed sequence has at least one step."),
+    };
+
+    sequence.push(mapping);
+    Ok(sequence)
+}
+
+/// Resolves the behaviours of a choice, either from a template or per key.
+fn choice_behaviours(
+    spec: &str,
+    keys: &[keyboard_hook_notation::KeyPress],
+    notation: &str,
+) -> Result<Vec<Behaviour<String>>, String> {
+    let spec = spec.trim();
+
+    let Some(block) = spec
+        .strip_prefix('{')
+        .and_then(|spec| spec.strip_suffix('}'))
+    else {
+        let template = Spec::parse(spec)?;
+
+        return Ok(keys
+            .iter()
+            .map(|key| template.instantiate(key.key).behaviour((*key).into()))
+            .collect());
+    };
+
+    let mut per_key: Vec<(keyboard_hook_notation::KeyPress, Spec)> = vec![];
+
+    for entry in split_entries(block) {
+        let Some((key, behaviour)) = entry.split_once("=>") else {
+            return Err(format!(
+                "expected `key => behaviour`, found `{}`",
+                entry.trim()
+            ));
+        };
+
+        let name = unquote(key);
+        let key = keyboard_hook_notation::parse_key_press(&name)
+            .ok()
+            .filter(|key| keys.contains(key))
+            .ok_or_else(|| format!("`{}` is not one of the choice's keys", name))?;
+
+        if per_key.iter().any(|(k, _)| *k == key) {
+            return Err(format!("`{}` is mapped twice", name));
+        }
+
+        per_key.push((key, Spec::parse(behaviour)?));
+    }
+
+    keys.iter()
+        .map(|key| {
+            per_key
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, spec)| spec.clone().behaviour((*key).into()))
+                .ok_or_else(|| format!("`{}` of `{}` is not mapped", key, notation))
+        })
+        .collect()
+}
+
+/// Parses a keymap written the way `keymap!` takes it, an entry per line. Lines starting with `#`
+/// are comments, and a `{ ... }` block of per-key behaviours may span several lines:
+///
+/// ```text
+/// # Mixer
+/// "<A-a> w" => Kenny
+/// "<A-a> q" => Princess on timeout
+/// "<A-a> e x i t" => shutdown
+/// "<A-a> [1-5]*" as ToggleChannels => ToggleChannel(n) on timeout
+/// "<A-a> [jk]*" as Volume => {
+///     j => VolumeDown,
+///     k => VolumeUp,
+/// }
+/// ```
+pub fn parse(source: &str) -> Result<ConfigMappings, ConfigError> {
+    parse_with(source, &|_| Ok(true))
+}
+
+/// Parses the keymap, failing on the first action `known` doesn't know or finds malformed.
+pub(crate) fn parse_with(
+    source: &str,
+    known: &dyn Fn(&str) -> Result<bool, String>,
+) -> Result<ConfigMappings, ConfigError> {
+    let mut mappings = vec![];
+    let mut entry = String::new();
+    let mut start = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+
+        if entry.is_empty() && (line.is_empty() || line.starts_with('#')) {
+            continue;
+        }
+
+        if entry.is_empty() {
+            start = i + 1;
+        }
+
+        entry.push_str(line);
+        entry.push(' ');
+
+        if is_block_open(&entry) {
+            continue;
+        }
+
+        let sequence = parse_entry(entry.trim()).map_err(|message| ConfigError::Syntax {
+            line: start,
+            message,
+        })?;
+
+        check_actions(&sequence, known, start)?;
+        mappings.push(sequence);
+        entry.clear();
+    }
+
+    if !entry.is_empty() {
+        return Err(ConfigError::Syntax {
+            line: start,
+            message: "the `{` block is never closed".to_string(),
+        });
+    }
+
+    resolve_prefixes(&mut mappings);
+    Ok(mappings)
+}
+
+/// Keys leading up to the last one of a sequence only trigger a timeout, unless another entry maps
+/// that exact prefix, wherever it's written, like `keymap!` does.
+fn resolve_prefixes(mappings: &mut ConfigMappings) {
+    let mut exact: HashMap<Vec<KeyPress>, Behaviour<String>> = HashMap::new();
+
+    for sequence in mappings.iter() {
+        let path: Option<Vec<KeyPress>> = sequence
+            .iter()
+            .map(|mapping| match mapping {
+                Mapping::Single(behaviour) => Some(behaviour.get_key()),
+                Mapping::Choice(_, _) => None,
+            })
+            .collect();
+
+        if let (Some(path), Some(Mapping::Single(behaviour))) = (path, sequence.last()) {
+            exact.entry(path).or_insert_with(|| behaviour.clone());
+        }
+    }
+
+    for sequence in mappings.iter_mut() {
+        let prefixes = sequence.len() - 1;
+        let mut path = vec![];
+
+        for mapping in &mut sequence[..prefixes] {
+            if let Mapping::Single(behaviour) = mapping {
+                path.push(behaviour.get_key());
+
+                if let Some(resolved) = exact.get(&path) {
+                    *behaviour = resolved.clone();
+                }
+            }
+        }
+    }
+}
+
+/// Checks the actions a sequence refers to. The actions of a choice whose tag is a known action
+/// are arguments of the tag rather than actions of their own.
+fn check_actions(
+    sequence: &[Mapping<String, String>],
+    known: &dyn Fn(&str) -> Result<bool, String>,
+    line: usize,
+) -> Result<(), ConfigError> {
+    let malformed = |action: &str, message: String| ConfigError::Syntax {
+        line,
+        message: format!("`{}`: {}", action, message),
+    };
+
+    for mapping in sequence {
+        let behaviours = match mapping {
+            Mapping::Single(behaviour) => std::slice::from_ref(behaviour),
+            Mapping::Choice(behaviours, tag) => match known(tag) {
+                Ok(true) => continue,
+                Ok(false) => behaviours.0.as_slice(),
+                Err(message) => return Err(malformed(tag, message)),
+            },
+        };
+
+        for behaviour in behaviours {
+            let (Behaviour::Action(_, action) | Behaviour::ActionOnTimeout(_, action)) = behaviour
+            else {
+                continue;
+            };
+
+            match known(action) {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(ConfigError::UnknownAction {
+                        line,
+                        action: action.clone(),
+                    })
+                }
+                Err(message) => return Err(malformed(action, message)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<ConfigMappings, ConfigError> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::mapping_trie::MappingTrie;
+
+    #[test]
+    fn should_parse_the_same_mappings_as_the_macro() {
+        // Given
+        let source = r#"
+            # Mixer
+            "<A-a> e x i t" => shutdown
+            "<A-a> w" => Kenny
+            "<A-a> q" => "Princess" on timeout
+            "<A-a> s 2 [1-3]*" as Channels => Toggle(n) on timeout
+            "<A-a> [jk]*" as Volume => {
+                j => VolumeDown,
+                k => VolumeUp,
+            }
+        "#;
+
+        // When
+        let mappings = parse(source).unwrap();
+
+        // Then
+        let expected: ConfigMappings = keymap! {
+            "<A-a> e x i t" => shutdown,
+            "<A-a> w" => "Kenny".to_string(),
+            "<A-a> q" => "Princess".to_string() on timeout,
+            "<A-a> s 2 [1-3]*" as "Channels".to_string() => format!("Toggle({})", n) on timeout,
+            "<A-a> [jk]*" as "Volume".to_string() => {
+                j => "VolumeDown".to_string(),
+                k => "VolumeUp".to_string(),
+            },
+        };
+        assert_eq!(mappings, expected);
+    }
+
+    #[test]
+    fn should_resolve_prefixes_mapped_after_the_sequences_through_them() {
+        // Given
+        let source = r#"
+            "<A-a> s 2 [1-3]*" as Channels => Toggle(n) on timeout
+            "<A-a> s 2" => UseStrip2 on timeout
+        "#;
+
+        // When
+        let mappings = parse(source).unwrap();
+
+        // Then
+        let expected: ConfigMappings = keymap! {
+            "<A-a> s 2 [1-3]*" as "Channels".to_string() => format!("Toggle({})", n) on timeout,
+            "<A-a> s 2" => "UseStrip2".to_string() on timeout,
+        };
+        assert_eq!(mappings, expected);
+        assert!(MappingTrie::new(&mappings.into()).1.is_empty());
+    }
+
+    #[test]
+    fn should_ignore_braces_in_quoted_actions() {
+        // Given
+        let source = r#"
+            "<A-a> o" => "Open {"
+            "<A-a> [jk]*" as Volume => {
+                j => "}",
+                k => VolumeUp,
+            }
+        "#;
+
+        // When
+        let mappings = parse(source).unwrap();
+
+        // Then
+        let expected: ConfigMappings = keymap! {
+            "<A-a> o" => "Open {".to_string(),
+            "<A-a> [jk]*" as "Volume".to_string() => {
+                j => "}".to_string(),
+                k => "VolumeUp".to_string(),
+            },
+        };
+        assert_eq!(mappings, expected);
+    }
+
+    #[test]
+    fn should_report_the_line_of_malformed_entries() {
+        let error = parse("\"<A-a> w\" => Kenny\n\n\"<A-a> [jk]*\" => Volume").unwrap_err();
+
+        assert!(matches!(
+            error,
+            ConfigError::Syntax { line: 3, message } if message.starts_with("a choice needs a tag")
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+use crate::mapping_trie::MappingTrie;
+use crate::mapping_trie::NodeView;
+use crate::types::format_keys;
+use crate::types::Behaviour;
+use crate::KeyPress;
+use core::hash::Hash;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::mem::discriminant;
+
+/// A difference between two keymaps, in terms of what pressing keys does.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Change<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Added {
+        path: Vec<KeyPress>,
+        behaviour: Behaviour<A>,
+    },
+    Removed {
+        path: Vec<KeyPress>,
+        behaviour: Behaviour<A>,
+    },
+    /// The same action moved to another sequence.
+    Rebound {
+        from: Vec<KeyPress>,
+        to: Vec<KeyPress>,
+        behaviour: Behaviour<A>,
+    },
+    /// The sequence fires at another time, e.g. on timeout instead of immediately.
+    KindChanged {
+        path: Vec<KeyPress>,
+        before: Behaviour<A>,
+        after: Behaviour<A>,
+    },
+    /// The sequence fires at the same time, but another action.
+    ActionChanged {
+        path: Vec<KeyPress>,
+        before: Behaviour<A>,
+        after: Behaviour<A>,
+    },
+    /// The choice after the prefix sends its actions under another tag.
+    Retagged {
+        prefix: Vec<KeyPress>,
+        before: T,
+        after: T,
+    },
+    /// Keys joined or left the choice after the prefix.
+    ChoiceChanged {
+        prefix: Vec<KeyPress>,
+        tag: T,
+        added: Vec<KeyPress>,
+        removed: Vec<KeyPress>,
+    },
+}
+
+impl<A, T> Display for Change<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, behaviour } => {
+                write!(f, "+ {} => {}", format_keys(path), behaviour.describe())
+            }
+            Change::Removed { path, behaviour } => {
+                write!(f, "- {} => {}", format_keys(path), behaviour.describe())
+            }
+            Change::Rebound {
+                from,
+                to,
+                behaviour,
+            } => write!(
+                f,
+                "> {} => {} (was {})",
+                format_keys(to),
+                behaviour.describe(),
+                format_keys(from)
+            ),
+            Change::KindChanged {
+                path,
+                before,
+                after,
+            }
+            | Change::ActionChanged {
+                path,
+                before,
+                after,
+            } => write!(
+                f,
+                "~ {} => {} (was {})",
+                format_keys(path),
+                after.describe(),
+                before.describe()
+            ),
+            Change::Retagged {
+                prefix,
+                before,
+                after,
+            } => write!(
+                f,
+                "~ {} [..]* as {} (was {})",
+                format_keys(prefix),
+                after,
+                before
+            ),
+            Change::ChoiceChanged {
+                prefix,
+                tag,
+                added,
+                removed,
+            } => {
+                write!(f, "~ {} [..]* as {}:", format_keys(prefix), tag)?;
+
+                if !added.is_empty() {
+                    write!(f, " + {}", format_keys(added))?;
+                }
+
+                if !removed.is_empty() {
+                    write!(f, " - {}", format_keys(removed))?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Flattened<'a, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    behaviours: BTreeMap<Vec<KeyPress>, &'a Behaviour<A>>,
+    choices: BTreeMap<Vec<KeyPress>, (&'a T, &'a [KeyPress])>,
+}
+
+fn flatten<A, T>(trie: &MappingTrie<A, T>) -> Flattened<'_, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn walk<'a, A, T>(
+        node: NodeView<'a, A, T>,
+        path: &mut Vec<KeyPress>,
+        result: &mut Flattened<'a, A, T>,
+    ) where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    {
+        for (key, child) in node.edges() {
+            if let Some(tag) = child.tag() {
+                result
+                    .choices
+                    .insert(path.clone(), (tag, child.repeatable_keys()));
+            }
+
+            path.push(key.clone());
+
+            if let Some(behaviour) = child.behaviour(key) {
+                result.behaviours.insert(path.clone(), behaviour);
+            }
+
+            walk(child, path, result);
+            path.pop();
+        }
+    }
+
+    let mut result = Flattened {
+        behaviours: BTreeMap::new(),
+        choices: BTreeMap::new(),
+    };
+    walk(trie.root(), &mut vec![], &mut result);
+    result
+}
+
+fn action<A>(behaviour: &Behaviour<A>) -> Option<&A>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    match behaviour {
+        Behaviour::Action(_, action) | Behaviour::ActionOnTimeout(_, action) => Some(action),
+        Behaviour::Timeout(_) | Behaviour::Shutdown(_) => None,
+    }
+}
+
+/// Whether the behaviours do the same, whichever key they're bound to.
+fn same<A>(before: &Behaviour<A>, after: &Behaviour<A>) -> bool
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    discriminant(before) == discriminant(after) && action(before) == action(after)
+}
+
+/// Compares two keymaps, e.g. before and after a change, sorted by sequence. Keys that only lead
+/// to other keys aren't reported on their own, and keys joining or leaving a choice are reported
+/// as a change of the choice, as is a change of its tag.
+pub fn diff<A, T>(before: &MappingTrie<A, T>, after: &MappingTrie<A, T>) -> Vec<Change<A, T>>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    let before = flatten(before);
+    let after = flatten(after);
+    let mut changes = vec![];
+
+    // Keys of choices found on both sides are covered by the choice's change.
+    let in_shared_choice = |path: &Vec<KeyPress>| {
+        let (key, prefix) = path.split_last().unwrap();
+
+        match (before.choices.get(prefix), after.choices.get(prefix)) {
+            (Some((_, old)), Some((_, new))) => old.contains(key) || new.contains(key),
+            _ => false,
+        }
+    };
+    let is_reported = |path: &Vec<KeyPress>, behaviour: &&Behaviour<A>| {
+        !matches!(behaviour, Behaviour::Timeout(_)) && !in_shared_choice(path)
+    };
+
+    let mut removed: Vec<_> = before
+        .behaviours
+        .iter()
+        .filter(|(path, behaviour)| {
+            !after.behaviours.contains_key(*path) && is_reported(path, behaviour)
+        })
+        .collect();
+    let mut added: Vec<_> = after
+        .behaviours
+        .iter()
+        .filter(|(path, behaviour)| {
+            !before.behaviours.contains_key(*path) && is_reported(path, behaviour)
+        })
+        .collect();
+
+    for (path, old) in &before.behaviours {
+        let Some(new) = after.behaviours.get(path) else {
+            continue;
+        };
+
+        if discriminant(*old) != discriminant(*new) {
+            changes.push(Change::KindChanged {
+                path: path.clone(),
+                before: (*old).clone(),
+                after: (*new).clone(),
+            });
+        } else if action(old) != action(new) {
+            changes.push(Change::ActionChanged {
+                path: path.clone(),
+                before: (*old).clone(),
+                after: (*new).clone(),
+            });
+        }
+    }
+
+    removed.retain(|(from, old)| {
+        let rebound = added
+            .iter()
+            .position(|(_, new)| action(old).is_some() && same(old, new));
+
+        match rebound {
+            Some(i) => {
+                let (to, new) = added.remove(i);
+                changes.push(Change::Rebound {
+                    from: (*from).clone(),
+                    to: to.clone(),
+                    behaviour: (*new).clone(),
+                });
+                false
+            }
+            None => true,
+        }
+    });
+
+    changes.extend(
+        removed
+            .into_iter()
+            .map(|(path, behaviour)| Change::Removed {
+                path: path.clone(),
+                behaviour: (*behaviour).clone(),
+            }),
+    );
+    changes.extend(added.into_iter().map(|(path, behaviour)| Change::Added {
+        path: path.clone(),
+        behaviour: (*behaviour).clone(),
+    }));
+
+    for (prefix, (old_tag, old_keys)) in &before.choices {
+        let Some((tag, new_keys)) = after.choices.get(prefix) else {
+            continue;
+        };
+
+        if old_tag != tag {
+            changes.push(Change::Retagged {
+                prefix: prefix.clone(),
+                before: (*old_tag).clone(),
+                after: (*tag).clone(),
+            });
+        }
+
+        let joined: Vec<_> = new_keys
+            .iter()
+            .filter(|k| !old_keys.contains(k))
+            .cloned()
+            .collect();
+        let left: Vec<_> = old_keys
+            .iter()
+            .filter(|k| !new_keys.contains(k))
+            .cloned()
+            .collect();
+
+        if !joined.is_empty() || !left.is_empty() {
+            changes.push(Change::ChoiceChanged {
+                prefix: prefix.clone(),
+                tag: (*tag).clone(),
+                added: joined,
+                removed: left,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| sort_key(a).cmp(sort_key(b)));
+    changes
+}
+
+fn sort_key<A, T>(change: &Change<A, T>) -> &Vec<KeyPress>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    match change {
+        Change::Added { path, .. }
+        | Change::Removed { path, .. }
+        | Change::KindChanged { path, .. }
+        | Change::ActionChanged { path, .. } => path,
+        Change::Rebound { to, .. } => to,
+        Change::Retagged { prefix, .. } | Change::ChoiceChanged { prefix, .. } => prefix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::types::Mapping;
+
+    fn trie(
+        mappings: Vec<Vec<Mapping<&'static str, &'static str>>>,
+    ) -> MappingTrie<&'static str, &'static str> {
+        MappingTrie::new(&mappings.into()).0
+    }
+
+    #[test]
+    fn should_report_semantic_changes() {
+        // Given
+        let before = trie(keymap! {
+            "<A-a> w" => "Kenny",
+            "<A-a> q" => "Princess",
+            "<A-a> x" => "Mute",
+            "<A-a> z" => "Solo",
+            "<A-a> [12]*" as "Channels" => "Toggle" on timeout,
+        });
+        let after = trie(keymap! {
+            "<A-a> k" => "Kenny",
+            "<A-a> q" => "Princess" on timeout,
+            "<A-a> x" => "Unmute",
+            "<A-a> y" => "Record",
+            "<A-a> [123]*" as "Channels" => "Toggle" on timeout,
+        });
+
+        // When
+        let changes: Vec<_> = diff(&before, &after)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+
+        // Then
+        assert_eq!(
+            changes,
+            [
+                "~ <A-A> [..]* as Channels: + 3",
+                "> <A-A> K => Kenny (was <A-A> W)",
+                "~ <A-A> Q => Princess on timeout (was Princess)",
+                "~ <A-A> X => Unmute (was Mute)",
+                "+ <A-A> Y => Record",
+                "- <A-A> Z => Solo",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_a_renamed_tag() {
+        // Given
+        let before = trie(keymap! { "<A-a> [12]*" as "Channels" => "Toggle" on timeout });
+        let after = trie(keymap! { "<A-a> [123]*" as "Mixer" => "Toggle" on timeout });
+
+        // When
+        let changes: Vec<_> = diff(&before, &after)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+
+        // Then
+        assert_eq!(
+            changes,
+            [
+                "~ <A-A> [..]* as Mixer (was Channels)",
+                "~ <A-A> [..]* as Mixer: + 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_find_no_changes_between_equal_keymaps() {
+        let keymap = || keymap! { "<A-a> w" => "Kenny", "<A-a> [12]*" as "Channels" => "Toggle" };
+
+        assert!(diff(&trie(keymap()), &trie(keymap())).is_empty());
+    }
+}
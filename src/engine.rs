@@ -0,0 +1,414 @@
+use crate::fragment::Mappings;
+use crate::mapping_manager::find_mapping;
+use crate::mapping_manager::Actions;
+use crate::mapping_trie::Cursor;
+use crate::mapping_trie::MappingLookup;
+use crate::mapping_trie::MappingTrie;
+use crate::static_trie::StaticMappingTrie;
+use crate::types::Conflict;
+use crate::types::Event;
+use crate::types::FlushPolicy;
+use crate::types::HookAction;
+use crate::types::HookAction::{PassOn, Suppress};
+use crate::types::SystemAction;
+use crate::KeyPress;
+use core::hash::Hash;
+use smallvec::smallvec;
+use smallvec::SmallVec;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(650);
+
+/// The events triggered by a key press, a timeout or stopping. There are at most two of them, so
+/// they're kept inline rather than allocated.
+pub type Events<A, T> = SmallVec<[Event<A, T>; 2]>;
+
+pub(crate) struct Buffers<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+{
+    pub cursor: Cursor,
+    pub actions_on_timeout: Actions<A, T>,
+}
+
+impl<A, T> Buffers<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            cursor: Cursor::root(),
+            actions_on_timeout: Actions::empty(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cursor = Cursor::root();
+        self.actions_on_timeout.clear();
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub(crate) enum KeyHandlerAction<A>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+{
+    Nothing, // TODO: Add reason for clarity.
+    Timeout,
+    Action(A),
+    ActionBeforeTimeout(A),
+    ActionOnTimeout(A),
+    /// The actions on timeout were aggregated in the buffers.
+    ActionsOnTimeout,
+    ActionsBeforeAndOnTimeout(A),
+    StopTheHook,
+}
+
+use KeyHandlerAction::*;
+
+impl<A> Display for KeyHandlerAction<A>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Nothing => write!(f, "Nothing"),
+            Timeout => write!(f, "Timeout"),
+            Action(action) => write!(f, "Action({})", action),
+            ActionBeforeTimeout(action) => {
+                write!(f, "ActionBeforeTimeout({})", action)
+            }
+            ActionOnTimeout(action) => {
+                write!(f, "ActionOnTimeout({})", action)
+            }
+            ActionsBeforeAndOnTimeout(before) => {
+                write!(f, "ActionsBeforeAndOnTimeout({})", before)
+            }
+            ActionsOnTimeout => write!(f, "ActionsOnTimeout"),
+            StopTheHook => write!(f, "StopTheHook"),
+        }
+    }
+}
+
+/// Matches key presses against the mappings and decides what happens to them, without any threads
+/// or channels. Whoever drives it feeds it key presses and calls `tick` once `next_deadline` has
+/// passed.
+///
+/// ```ignore
+/// let (mut engine, _) = Engine::new(mappings);
+/// let (hook_action, events) = engine.feed(&key_press, Instant::now());
+///
+/// if engine.next_deadline().is_some_and(|deadline| deadline <= Instant::now()) {
+///     let events = engine.tick(Instant::now());
+/// }
+/// ```
+pub struct Engine<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    mapping_trie: Box<dyn MappingLookup<A, T> + Send>,
+    buffers: Buffers<A, T>,
+    timeout: Duration,
+    deadline: Option<Instant>,
+    timeout_action: Option<A>,
+    stopped: bool,
+    log_unmapped_keys: bool,
+}
+
+impl<A, T> Engine<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    /// Builds the mapping trie. Conflicting sequences are left out and returned.
+    pub fn new(mappings: impl Into<Mappings<A, T>>) -> (Self, Vec<Conflict>) {
+        let (trie, conflicts) = MappingTrie::new(&mappings.into());
+
+        (Self::from_trie(trie), conflicts)
+    }
+
+    /// Uses a trie that was already built, e.g. one that's also inspected with `MappingTrie::node`.
+    pub fn from_trie(trie: MappingTrie<A, T>) -> Self {
+        Self::from_lookup(Box::new(trie))
+    }
+
+    pub fn from_static(trie: &'static StaticMappingTrie<A, T>) -> Self {
+        Self::from_lookup(Box::new(trie))
+    }
+
+    pub(crate) fn from_lookup(mapping_trie: Box<dyn MappingLookup<A, T> + Send>) -> Self {
+        Self {
+            mapping_trie,
+            buffers: Buffers::new(),
+            timeout: DEFAULT_TIMEOUT,
+            deadline: None,
+            timeout_action: None,
+            stopped: false,
+            log_unmapped_keys: false,
+        }
+    }
+
+    /// How long to wait for the next key of a sequence.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn log_unmapped_keys(mut self, enabled: bool) -> Self {
+        self.log_unmapped_keys = enabled;
+        self
+    }
+
+    /// When `tick` should be called next, if a sequence is waiting for a timeout.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Where the keys pressed since the last reset led in the trie.
+    pub fn cursor(&self) -> Cursor {
+        self.buffers.cursor
+    }
+
+    /// The event `tick` would send if the deadline passed now.
+    pub fn pending(&self) -> Option<Event<A, T>> {
+        match &self.timeout_action {
+            Some(action) => Some(Event::Single(action.clone())),
+            None => {
+                let actions = &self.buffers.actions_on_timeout;
+
+                actions
+                    .tag
+                    .clone()
+                    .map(|tag| Event::Multi(tag, actions.actions.clone()))
+            }
+        }
+    }
+
+    /// Whether a `shutdown` mapping was hit or `stop` was called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Handles a key press. Returns whether the key press should be suppressed, along with the
+    /// events it triggered right away.
+    pub fn feed(&mut self, key_press: &KeyPress, now: Instant) -> (HookAction, Events<A, T>) {
+        if self.stopped {
+            return (PassOn, Events::new());
+        }
+
+        let handler_action = find_mapping(key_press, &*self.mapping_trie, &mut self.buffers);
+
+        if let Nothing = handler_action {
+            if self.log_unmapped_keys {
+                tracing::debug!(key = %key_press, "Unmapped key");
+            }
+        } else {
+            tracing::debug!(key = %key_press, action = %handler_action, "Mapped key");
+        }
+
+        match handler_action {
+            Nothing => {
+                if self.deadline.is_some() {
+                    tracing::debug!("Sequence reset");
+                }
+
+                self.reset();
+                (PassOn, Events::new())
+            }
+            Timeout => {
+                self.deadline = Some(now + self.timeout);
+                (Suppress, Events::new())
+            }
+            Action(action) => {
+                self.reset();
+                (Suppress, smallvec![Event::Single(action)])
+            }
+            ActionBeforeTimeout(action) | ActionsBeforeAndOnTimeout(action) => {
+                self.timeout_action = None;
+                self.deadline = Some(now + self.timeout);
+                (Suppress, smallvec![Event::Single(action)])
+            }
+            ActionOnTimeout(action) => {
+                self.timeout_action = Some(action);
+                self.deadline = Some(now + self.timeout);
+                (Suppress, Events::new())
+            }
+            ActionsOnTimeout => {
+                self.timeout_action = None;
+                self.deadline = Some(now + self.timeout);
+                (Suppress, Events::new())
+            }
+            StopTheHook => {
+                self.reset();
+                self.stopped = true;
+                (
+                    Suppress,
+                    smallvec![Event::System(SystemAction::KeyboardUnhooked)],
+                )
+            }
+        }
+    }
+
+    /// Fires the actions waiting for a timeout once the deadline has passed.
+    pub fn tick(&mut self, now: Instant) -> Events<A, T> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {
+                tracing::debug!(
+                    action = ?self.timeout_action,
+                    actions = %self.buffers.actions_on_timeout,
+                    "Timeout"
+                );
+
+                let events = self.take_actions_on_timeout().into_iter().collect();
+                self.reset();
+                events
+            }
+            _ => Events::new(),
+        }
+    }
+
+    /// Stops matching key presses. Actions waiting for a timeout are sent or dropped according to
+    /// the policy.
+    pub fn stop(&mut self, policy: FlushPolicy) -> Events<A, T> {
+        if self.stopped {
+            return Events::new();
+        }
+
+        let mut events: Events<A, T> = match (policy, self.deadline) {
+            (FlushPolicy::Flush, Some(_)) => self.take_actions_on_timeout().into_iter().collect(),
+            _ => Events::new(),
+        };
+
+        self.reset();
+        self.stopped = true;
+        events.push(Event::System(SystemAction::KeyboardUnhooked));
+        events
+    }
+
+    /// Moves the actions waiting for a timeout into an event, leaving the buffers empty.
+    fn take_actions_on_timeout(&mut self) -> Option<Event<A, T>> {
+        if let Some(timeout_action) = self.timeout_action.take() {
+            Some(Event::Single(timeout_action))
+        } else {
+            let actions = &mut self.buffers.actions_on_timeout;
+
+            actions
+                .tag
+                .take()
+                .map(|tag| Event::Multi(tag, std::mem::take(&mut actions.actions)))
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffers.clear();
+        self.timeout_action = None;
+        self.deadline = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::types::Key::*;
+    use crate::*;
+
+    fn engine() -> Engine<&'static str, &'static str> {
+        let (engine, conflicts) = Engine::new(keymap! {
+            "<A-a> w" => "Kenny",
+            "<A-a> q" => "Princess" on timeout,
+            "<A-a> [12]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two" on timeout },
+        });
+        assert!(conflicts.is_empty());
+
+        engine
+    }
+
+    #[test]
+    fn should_send_immediate_actions_right_away() {
+        // Given
+        let mut engine = engine();
+        let now = Instant::now();
+
+        // When
+        let first = engine.feed(&alt!(KeyA), now);
+        let second = engine.feed(&key!(KeyW), now);
+
+        // Then
+        assert_eq!(first, (Suppress, smallvec![]));
+        assert_eq!(second, (Suppress, smallvec![Event::Single("Kenny")]));
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn should_send_actions_on_timeout_once_the_deadline_passes() {
+        // Given
+        let mut engine = engine();
+        let now = Instant::now();
+        engine.feed(&alt!(KeyA), now);
+        engine.feed(&key!(Key1), now);
+        engine.feed(&key!(Key2), now);
+        let deadline = engine.next_deadline().unwrap();
+        let pending = engine.pending();
+
+        // When
+        let early = engine.tick(deadline - Duration::from_millis(1));
+        let on_time = engine.tick(deadline);
+
+        // Then
+        assert_eq!(deadline, now + DEFAULT_TIMEOUT);
+        assert!(early.is_empty());
+        assert_eq!(pending, Some(Event::Multi("Channels", vec!["One", "Two"])));
+        assert_eq!(engine.pending(), None);
+        assert_eq!(on_time[..], [Event::Multi("Channels", vec!["One", "Two"])]);
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn should_aggregate_long_choice_runs() {
+        // Given
+        let mut engine = engine();
+        let now = Instant::now();
+        engine.feed(&alt!(KeyA), now);
+
+        // When
+        for _ in 0..1000 {
+            engine.feed(&key!(Key1), now);
+            engine.feed(&key!(Key2), now);
+        }
+
+        // Then
+        let [Event::Multi("Channels", actions)] = &engine.tick(now + DEFAULT_TIMEOUT)[..] else {
+            panic!("Expected the channels to be toggled");
+        };
+        assert_eq!(actions.len(), 2000);
+        assert_eq!(&actions[1998..], ["One", "Two"]);
+    }
+
+    #[test]
+    fn should_pass_unmapped_keys_on_and_reset_the_sequence() {
+        // Given
+        let mut engine = engine();
+        let now = Instant::now();
+        engine.feed(&alt!(KeyA), now);
+        engine.feed(&key!(KeyQ), now);
+
+        // When
+        let result = engine.feed(&key!(KeyX), now);
+
+        // Then
+        assert_eq!(result, (PassOn, smallvec![]));
+        assert_eq!(engine.next_deadline(), None);
+        assert!(engine.tick(now + DEFAULT_TIMEOUT).is_empty());
+    }
+}
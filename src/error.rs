@@ -0,0 +1,133 @@
+use std::fmt;
+use std::sync::mpsc::SendError;
+use std::sync::PoisonError;
+
+#[derive(Debug)]
+pub enum KeyboardHookError {
+    /// The OS refused to install the hook.
+    InstallFailed(std::io::Error),
+    /// Only one keyboard hook can be installed per process.
+    AlreadyInstalled,
+    /// Anything else going wrong in the platform backend.
+    Backend(String),
+    /// There's no backend for this platform, only Windows has one.
+    UnsupportedPlatform,
+    /// The action handler stopped receiving events.
+    HandlerDisconnected,
+    /// A thread panicked while holding the key handler's state.
+    PoisonedState,
+    /// The key handler's state stayed locked for longer than the budget allows.
+    LockTimeout,
+}
+
+impl fmt::Display for KeyboardHookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyboardHookError::InstallFailed(error) => {
+                write!(f, "Failed to install keyboard hook: {}", error)
+            }
+            KeyboardHookError::AlreadyInstalled => write!(f, "Keyboard hook is already installed."),
+            KeyboardHookError::Backend(message) => write!(f, "Keyboard hook failed: {}", message),
+            KeyboardHookError::UnsupportedPlatform => {
+                write!(f, "Hooking the keyboard is only supported on Windows.")
+            }
+            KeyboardHookError::HandlerDisconnected => {
+                write!(f, "The action handler stopped receiving events.")
+            }
+            KeyboardHookError::PoisonedState => write!(f, "The key handler's state is poisoned."),
+            KeyboardHookError::LockTimeout => {
+                write!(f, "Timed out waiting for the key handler's state.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeyboardHookError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeyboardHookError::InstallFailed(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<SendError<T>> for KeyboardHookError {
+    fn from(_: SendError<T>) -> Self {
+        KeyboardHookError::HandlerDisconnected
+    }
+}
+
+impl<T> From<PoisonError<T>> for KeyboardHookError {
+    fn from(_: PoisonError<T>) -> Self {
+        KeyboardHookError::PoisonedState
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The keymap file couldn't be read.
+    Io(std::io::Error),
+    /// An entry of the keymap is malformed, the line being the one it starts on.
+    Syntax { line: usize, message: String },
+    /// An entry refers to an action nobody registered, see `ActionRegistry`.
+    UnknownAction { line: usize, action: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Failed to read the keymap: {}", error),
+            ConfigError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ConfigError::UnknownAction { line, action } => {
+                write!(f, "line {}: no action is registered as `{}`", line, action)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    /// The journal couldn't be read.
+    Io(std::io::Error),
+    /// A record of the journal is malformed.
+    Malformed { line: usize, message: String },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Io(error) => write!(f, "Failed to read the journal: {}", error),
+            JournalError::Malformed { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JournalError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for JournalError {
+    fn from(error: std::io::Error) -> Self {
+        JournalError::Io(error)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::mapping_trie::MappingTrie;
+use crate::mapping_trie::NodeKind;
+use crate::mapping_trie::NodeView;
+use crate::types::format_keys;
+use crate::types::Behaviour;
+use crate::KeyPress;
+use core::hash::Hash;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Write;
+
+/// What pressing the key to reach the node does, along with the choice it belongs to.
+fn describe_node<A, T>(key: &KeyPress, node: &NodeView<'_, A, T>) -> String
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    let behaviour = node
+        .behaviour(key)
+        .map(Behaviour::describe)
+        .unwrap_or_default();
+
+    match node.tag() {
+        Some(tag) => format!(
+            "{} ({}: {})",
+            behaviour,
+            tag,
+            format_keys(node.repeatable_keys())
+        ),
+        None => behaviour,
+    }
+}
+
+/// Writes the trie as an indented tree, a key per line along with what it does. Keys are sorted,
+/// so the output of two versions of a keymap can be diffed.
+///
+/// ```text
+/// <A-A> => timeout
+///   1 => One on timeout (Channels: 1 2)
+///   2 => Two on timeout (Channels: 1 2)
+///   W => Kenny
+/// ```
+pub fn to_text_tree<A, T>(trie: &MappingTrie<A, T>) -> String
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn write_node<A, T>(out: &mut String, node: NodeView<'_, A, T>, depth: usize)
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    {
+        for (key, child) in node.edges() {
+            let _ = writeln!(
+                out,
+                "{:indent$}{} => {}",
+                "",
+                key,
+                describe_node(key, &child),
+                indent = depth * 2
+            );
+            write_node(out, child, depth + 1);
+        }
+    }
+
+    let mut out = String::new();
+    write_node(&mut out, trie.root(), 0);
+    out
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Writes the trie as a Graphviz graph, e.g. to be rendered with `dot -Tsvg`. Keys of a choice
+/// are dashed loops on the nodes they keep the cursor on.
+pub fn to_dot<A, T>(trie: &MappingTrie<A, T>) -> String
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn write_node<A, T>(out: &mut String, node: NodeView<'_, A, T>)
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    {
+        for (key, child) in node.edges() {
+            let style = match child.kind() {
+                NodeKind::Repeatable => "rounded,dashed",
+                _ => "rounded",
+            };
+            let _ = writeln!(
+                out,
+                "    n{} [label=\"{}\", style=\"{}\"];",
+                child.id(),
+                escape(&describe_node(key, &child)),
+                style
+            );
+            let _ = writeln!(
+                out,
+                "    n{} -> n{} [label=\"{}\"];",
+                node.id(),
+                child.id(),
+                escape(&key.to_string())
+            );
+
+            if !child.repeatable_keys().is_empty() {
+                let _ = writeln!(
+                    out,
+                    "    n{0} -> n{0} [label=\"{1}\", style=dashed];",
+                    child.id(),
+                    escape(&format_keys(child.repeatable_keys()))
+                );
+            }
+
+            write_node(out, child);
+        }
+    }
+
+    let mut out = String::from("digraph keymap {\n");
+    out.push_str("    node [shape=box];\n");
+    let _ = writeln!(out, "    n{} [label=\"\", shape=point];", trie.root().id());
+    write_node(&mut out, trie.root());
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+
+    fn trie() -> MappingTrie<&'static str, &'static str> {
+        let (trie, _) = MappingTrie::new(
+            &keymap! {
+                "<A-a> w" => "Kenny",
+                "<A-a> [12]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two" on timeout },
+            }
+            .into(),
+        );
+
+        trie
+    }
+
+    #[test]
+    fn should_write_an_indented_tree() {
+        assert_eq!(
+            to_text_tree(&trie()),
+            "<A-A> => timeout\n\
+             \x20 1 => One on timeout (Channels: 1 2)\n\
+             \x20 2 => Two on timeout (Channels: 1 2)\n\
+             \x20 W => Kenny\n"
+        );
+    }
+
+    #[test]
+    fn should_write_a_graphviz_graph() {
+        let dot = to_dot(&trie());
+
+        assert!(dot.starts_with("digraph keymap {\n"));
+        assert!(dot.contains("    n0 -> n1 [label=\"<A-A>\"];\n"));
+        assert!(dot.contains("    n3 -> n3 [label=\"1 2\", style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
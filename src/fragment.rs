@@ -0,0 +1,182 @@
+use crate::types::Behaviour;
+use crate::types::KeyPress;
+use crate::types::Mapping;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// A named set of sequences that can be mounted under any number of prefixes, e.g. a choice of
+/// channels used both directly and after picking a strip.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Fragment<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    name: String,
+    sequences: Vec<Vec<Mapping<A, T>>>,
+}
+
+impl<A, T> Fragment<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    pub fn new(name: &str, sequences: Vec<Vec<Mapping<A, T>>>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.to_string(),
+            sequences,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sequences(&self) -> &Vec<Vec<Mapping<A, T>>> {
+        &self.sequences
+    }
+}
+
+type Mount<A, T> = (Vec<KeyPress>, Arc<Fragment<A, T>>);
+
+/// Sequences to build the mapping trie from, along with fragments mounted under prefixes.
+/// Fragments are expanded when the trie is built.
+///
+/// ```ignore
+/// let channels = Fragment::new("channels", keymap! {
+///     "[1-5]*" as ToggleChannels => ToggleChannel(n) on timeout,
+/// });
+///
+/// Mappings::new(keymap! { "<A-a> s 2" => UseStrip2 on timeout })
+///     .mount(&[alt!(KeyA)], &channels)
+///     .mount(&[alt!(KeyA), key!(KeyS), key!(Key2)], &channels)
+/// ```
+pub struct Mappings<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    pub(crate) sequences: Vec<Vec<Mapping<A, T>>>,
+    pub(crate) mounts: Vec<Mount<A, T>>,
+}
+
+impl<A, T> Mappings<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    pub fn new(sequences: Vec<Vec<Mapping<A, T>>>) -> Self {
+        Self {
+            sequences,
+            mounts: vec![],
+        }
+    }
+
+    /// Mounts the fragment's sequences after the given keys. The keys keep what the sequences
+    /// map them to, e.g. an action on timeout.
+    pub fn mount(mut self, prefix: &[KeyPress], fragment: &Arc<Fragment<A, T>>) -> Self {
+        self.mounts.push((prefix.to_vec(), fragment.clone()));
+        self
+    }
+
+    /// What the sequences map each key of the path to, a timeout for keys only leading further.
+    pub(crate) fn prefix(&self, path: &[KeyPress]) -> Vec<Mapping<A, T>> {
+        (1..=path.len())
+            .map(|depth| {
+                let mut behaviours = self.sequences.iter().filter_map(|sequence| {
+                    let keys = sequence.get(..depth)?.iter().map(|mapping| match mapping {
+                        Mapping::Single(behaviour) => Some(behaviour.get_key()),
+                        Mapping::Choice(_, _) => None,
+                    });
+
+                    if !keys.eq(path[..depth].iter().cloned().map(Some)) {
+                        return None;
+                    }
+
+                    match &sequence[depth - 1] {
+                        Mapping::Single(behaviour) => Some(behaviour),
+                        Mapping::Choice(_, _) => None,
+                    }
+                });
+                let behaviour = behaviours
+                    .find(|behaviour| !matches!(behaviour, Behaviour::Timeout(_)))
+                    .cloned()
+                    .unwrap_or_else(|| Behaviour::Timeout(path[depth - 1].clone()));
+
+                Mapping::Single(behaviour)
+            })
+            .collect()
+    }
+}
+
+impl<A, T> From<Vec<Vec<Mapping<A, T>>>> for Mappings<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn from(sequences: Vec<Vec<Mapping<A, T>>>) -> Self {
+        Self::new(sequences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alt;
+    use crate::keymap;
+    use crate::mapping_trie::MappingLookup;
+    use crate::mapping_trie::MappingTrie;
+    use crate::types::Key::*;
+
+    fn channels() -> Arc<Fragment<&'static str, &'static str>> {
+        Fragment::new(
+            "channels",
+            keymap! { "[1-2]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two" on timeout } },
+        )
+    }
+
+    #[test]
+    fn should_expand_fragments_under_every_mount_point() {
+        let channels = channels();
+        let mappings = Mappings::new(keymap! {
+            "<A-a> w" => "Kenny",
+            "<A-a> s 2" => "UseStrip2" on timeout,
+        })
+        .mount(&[alt!(KeyA)], &channels)
+        .mount(&[alt!(KeyA), KeyS.into(), Key2.into()], &channels);
+
+        let (trie, conflicts) = MappingTrie::new(&mappings);
+        let one = Behaviour::ActionOnTimeout(KeyPress::from(Key1), "One");
+        let strip = Behaviour::ActionOnTimeout(KeyPress::from(Key2), "UseStrip2");
+
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            trie.find_behaviour(&Key2.into(), &[alt!(KeyA), KeyS.into()]),
+            Some((&strip, None))
+        );
+        assert_eq!(
+            trie.find_behaviour(&Key1.into(), &[alt!(KeyA)]),
+            Some((&one, Some(&"Channels")))
+        );
+        assert_eq!(
+            trie.find_behaviour(&Key1.into(), &[alt!(KeyA), KeyS.into(), Key2.into()]),
+            Some((&one, Some(&"Channels")))
+        );
+    }
+
+    #[test]
+    fn should_report_conflicts_with_the_mount_point() {
+        let mappings =
+            Mappings::new(keymap! { "<A-a> 2" => "Kenny" }).mount(&[alt!(KeyA)], &channels());
+
+        let (_, conflicts) = MappingTrie::new(&mappings);
+
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "<A-A> 2: not all keys of the choice (Channels) are available \
+             (in fragment 'channels' mounted at <A-A>)"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::backend::HookThread;
+use crate::error::KeyboardHookError;
+use crate::runtime::Latency;
+use crate::types::FlushPolicy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Controls a hook started with `KeyboardHook::start`. Can be shared between threads.
+pub struct HookHandle {
+    pub(crate) thread: HookThread,
+    pub(crate) paused: Arc<AtomicBool>,
+    pub(crate) running: Arc<AtomicBool>,
+    pub(crate) latency: Arc<Latency>,
+    pub(crate) producer: JoinHandle<Result<(), KeyboardHookError>>,
+    pub(crate) consumer: JoinHandle<()>,
+}
+
+impl HookHandle {
+    /// Unhooks the keyboard, sending the actions waiting for a timeout first.
+    pub fn stop(&self) {
+        self.stop_with(FlushPolicy::default());
+    }
+
+    pub fn stop_with(&self, policy: FlushPolicy) {
+        self.thread.stop(policy);
+    }
+
+    /// Passes all key presses on until resumed. The keyboard stays hooked.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// How long the hook callback took to handle key presses.
+    pub fn latency(&self) -> &Latency {
+        &self.latency
+    }
+
+    /// Waits until the keyboard is unhooked and the handler has received all events.
+    pub fn join(self) -> Result<(), KeyboardHookError> {
+        let result = self.producer.join().unwrap_or_else(|_| {
+            Err(KeyboardHookError::Backend(
+                "the hook thread panicked".into(),
+            ))
+        });
+
+        match self.consumer.join() {
+            Ok(()) => result,
+            Err(_) => result.and(Err(KeyboardHookError::HandlerDisconnected)),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use crate::engine::Engine;
+use crate::engine::Events;
+use crate::error::JournalError;
+use crate::key_handler::key_press;
+use crate::types::Event;
+use crate::types::FlushPolicy;
+use crate::types::HookAction;
+use crate::types::Modifier;
+use core::hash::Hash;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::LineWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+const HEADER: &str = "# keyboard_hook journal v1";
+
+/// Records raw key events, what was decided for them and the events sent, one line each, so a
+/// session can be replayed offline with [`replay`]. Every record starts with its kind and the
+/// microseconds since the journal was opened:
+///
+/// ```text
+/// T 0 650000               timeout of the engine
+/// K 1200 65 A              key code with Alt (A), Shift (S), both (AS) or none (-)
+/// K 1300 87 - paused       key pressed while the hook was paused
+/// D 1250 suppress          suppress or pass, for the key before
+/// E 651300 single Kenny    single, multi or system event, written with `Display`
+/// E 1260 system OverBudget(12ms)   handling the key before took too long, not replayed
+/// S 700000 flush           the hook was stopped, flushing or discarding
+/// ```
+///
+/// The journal records everything typed while it's on, so it's never enabled by default.
+pub struct Journal {
+    start: Instant,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Journal {
+    pub fn new(out: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut out: Box<dyn Write + Send> = Box::new(out);
+        writeln!(out, "{}", HEADER)?;
+
+        Ok(Self {
+            start: Instant::now(),
+            out: Mutex::new(out),
+        })
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(LineWriter::new(File::create(path)?))
+    }
+
+    pub(crate) fn timeout(&self, timeout: Duration) {
+        self.write('T', format_args!("{}", timeout.as_micros()));
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn key(&self, key: u32, modifiers: &[Modifier], paused: bool) {
+        let modifiers = match (
+            modifiers.contains(&Modifier::ModAlt),
+            modifiers.contains(&Modifier::ModShift),
+        ) {
+            (true, true) => "AS",
+            (true, false) => "A",
+            (false, true) => "S",
+            (false, false) => "-",
+        };
+        let paused = if paused { " paused" } else { "" };
+
+        self.write('K', format_args!("{} {}{}", key, modifiers, paused));
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn decision(&self, hook_action: HookAction) {
+        self.write('D', format_args!("{}", decision(hook_action)));
+    }
+
+    pub(crate) fn event<A, T>(&self, event: &Event<A, T>)
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    {
+        self.write('E', format_args!("{}", describe(event)));
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn stop(&self, policy: FlushPolicy) {
+        let policy = match policy {
+            FlushPolicy::Flush => "flush",
+            FlushPolicy::Discard => "discard",
+        };
+
+        self.write('S', format_args!("{}", policy));
+    }
+
+    fn write(&self, kind: char, record: fmt::Arguments) {
+        let at = self.start.elapsed().as_micros();
+        let mut out = self
+            .out
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Err(error) = writeln!(out, "{} {} {}", kind, at, record) {
+            tracing::warn!(%error, "Failed to write to the journal");
+        }
+    }
+}
+
+fn decision(hook_action: HookAction) -> &'static str {
+    match hook_action {
+        HookAction::Suppress => "suppress",
+        HookAction::PassOn => "pass",
+    }
+}
+
+fn describe<A, T>(event: &Event<A, T>) -> String
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    let kind = match event {
+        Event::Single(_) => "single",
+        Event::Multi(_, _) => "multi",
+        Event::System(_) => "system",
+    };
+
+    format!("{} {}", kind, event)
+}
+
+enum Record {
+    Timeout(Duration),
+    Key {
+        key: u32,
+        modifiers: Vec<Modifier>,
+        paused: bool,
+    },
+    Outcome(String),
+    /// Depends on how long handling a key took, which the replay can't reproduce.
+    OverBudget,
+    Stop(FlushPolicy),
+}
+
+fn parse(line: &str) -> Result<(Duration, Record), String> {
+    let mut fields = line.splitn(3, ' ');
+    let kind = fields.next().unwrap_or_default();
+    let at = fields
+        .next()
+        .and_then(|at| at.parse().ok())
+        .map(Duration::from_micros)
+        .ok_or("missing the time of the record")?;
+    let rest = fields.next().unwrap_or_default();
+
+    let record = match kind {
+        "T" => Record::Timeout(Duration::from_micros(
+            rest.parse()
+                .map_err(|_| format!("`{}` is not a timeout", rest))?,
+        )),
+        "K" => {
+            let fields: Vec<_> = rest.split(' ').collect();
+            let (key, modifiers, paused) = match fields.as_slice() {
+                [key, modifiers] => (key, modifiers, false),
+                [key, modifiers, "paused"] => (key, modifiers, true),
+                _ => return Err(format!("`{}` is not a key", rest)),
+            };
+            let modifiers = match *modifiers {
+                "AS" => vec![Modifier::ModAlt, Modifier::ModShift],
+                "A" => vec![Modifier::ModAlt],
+                "S" => vec![Modifier::ModShift],
+                "-" => vec![],
+                other => return Err(format!("`{}` are not modifiers", other)),
+            };
+
+            Record::Key {
+                key: key
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a key code", key))?,
+                modifiers,
+                paused,
+            }
+        }
+        "E" if rest.starts_with("system OverBudget(") => Record::OverBudget,
+        "D" | "E" => Record::Outcome(format!("{} {}", kind, rest)),
+        "S" => Record::Stop(match rest {
+            "flush" => FlushPolicy::Flush,
+            "discard" => FlushPolicy::Discard,
+            other => return Err(format!("`{}` is not a flush policy", other)),
+        }),
+        other => return Err(format!("`{}` is not a kind of record", other)),
+    };
+
+    Ok((at, record))
+}
+
+/// What the replay did differently for a key, or for a stop, than the journal recorded.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Divergence {
+    /// The line of the key or stop record, 0 for what happened before the first one.
+    pub line: usize,
+    pub record: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} ({}): expected [{}], got [{}]",
+            self.line,
+            self.record,
+            self.expected.join("; "),
+            self.actual.join("; ")
+        )
+    }
+}
+
+/// Feeds a journal back through the engine on a virtual clock, each key at the time it was
+/// recorded, and compares the decisions and events with the recorded ones. Actions are compared
+/// by their `Display`, so the engine should be built from the keymap the journal was recorded
+/// with.
+pub fn replay<A, T>(journal: &str, engine: Engine<A, T>) -> Result<Vec<Divergence>, JournalError>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    let origin = Instant::now();
+    let mut engine = engine;
+    let mut groups = vec![Divergence {
+        line: 0,
+        record: String::new(),
+        expected: vec![],
+        actual: vec![],
+    }];
+    let mut last = Duration::ZERO;
+
+    for (index, line) in journal.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (at, record) = parse(line).map_err(|message| JournalError::Malformed {
+            line: index + 1,
+            message,
+        })?;
+        last = last.max(at);
+
+        // The timer fires before the next key arrives, so its events belong to the key before.
+        if !matches!(
+            record,
+            Record::Outcome(_) | Record::Timeout(_) | Record::OverBudget
+        ) {
+            for event in engine.tick(origin + at) {
+                push_actual(&mut groups, format!("E {}", describe(&event)));
+            }
+
+            groups.push(Divergence {
+                line: index + 1,
+                record: line.to_string(),
+                expected: vec![],
+                actual: vec![],
+            });
+        }
+
+        match record {
+            Record::Timeout(timeout) => engine = engine.with_timeout(timeout),
+            Record::Key {
+                key,
+                modifiers,
+                paused,
+            } => {
+                let key_press = key_press(key, &modifiers).filter(|_| !paused);
+                let (hook_action, events) = match key_press {
+                    Some(key_press) => engine.feed(&key_press, origin + at),
+                    None => (HookAction::PassOn, Events::new()),
+                };
+
+                for event in events {
+                    push_actual(&mut groups, format!("E {}", describe(&event)));
+                }
+
+                push_actual(&mut groups, format!("D {}", decision(hook_action)));
+            }
+            Record::OverBudget => {}
+            Record::Outcome(outcome) => {
+                if let Some(group) = groups.last_mut() {
+                    group.expected.push(outcome);
+                }
+            }
+            Record::Stop(policy) => {
+                for event in engine.stop(policy) {
+                    push_actual(&mut groups, format!("E {}", describe(&event)));
+                }
+            }
+        }
+    }
+
+    for event in engine.tick(origin + last) {
+        push_actual(&mut groups, format!("E {}", describe(&event)));
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|group| group.expected != group.actual)
+        .collect())
+}
+
+fn push_actual(groups: &mut [Divergence], outcome: String) {
+    if let Some(group) = groups.last_mut() {
+        group.actual.push(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn engine() -> Engine<&'static str, &'static str> {
+        let (engine, _) = Engine::new(keymap! {
+            "<A-a> w" => "Kenny",
+            "<A-a> q" => "Princess" on timeout,
+        });
+
+        engine
+    }
+
+    const JOURNAL: &str = "# keyboard_hook journal v1
+T 0 650000
+K 1000 65 A
+E 1005 system OverBudget(12ms)
+D 1010 suppress
+K 2000 81 -
+D 2010 suppress
+E 652500 single Princess
+K 700000 87 - paused
+D 700010 pass
+S 800000 flush
+E 800010 system ShuttingDown
+";
+
+    #[test]
+    fn should_write_one_line_per_record() {
+        // Given
+        let buffer = Shared(Arc::new(Mutex::new(vec![])));
+        let journal = Journal::new(buffer.clone()).unwrap();
+
+        // When
+        journal.key(65, &[Modifier::ModAlt, Modifier::ModShift], false);
+        journal.decision(HookAction::Suppress);
+        journal.event(&Event::<&str, &str>::Multi("Channels", vec!["One", "Two"]));
+
+        // Then
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let records: Vec<_> = written
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields: Vec<_> = line.splitn(3, ' ').collect();
+                format!("{} {}", fields[0], fields.get(2).unwrap_or(&""))
+            })
+            .collect();
+        assert_eq!(
+            records,
+            ["K 65 AS", "D suppress", "E multi Channels: One, Two"]
+        );
+    }
+
+    #[test]
+    fn should_replay_a_journal_without_divergences() {
+        assert_eq!(replay(JOURNAL, engine()).unwrap(), []);
+    }
+
+    #[test]
+    fn should_report_where_the_replay_diverges() {
+        // Given
+        let (engine, _) = Engine::<_, &str>::new(keymap! { "<A-a> q" => "Queen" on timeout });
+
+        // When
+        let divergences = replay(JOURNAL, engine).unwrap();
+
+        // Then
+        assert_eq!(
+            divergences,
+            [Divergence {
+                line: 6,
+                record: "K 2000 81 -".to_string(),
+                expected: vec!["D suppress".to_string(), "E single Princess".to_string()],
+                actual: vec!["D suppress".to_string(), "E single Queen".to_string()],
+            }]
+        );
+    }
+}
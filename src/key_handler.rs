@@ -1,70 +1,42 @@
-use crate::keypress_buffer::KeyPressBuffer;
-use crate::mapping_manager::find_mapping;
-use crate::mapping_manager::Actions;
-use crate::mapping_trie::MappingTrie;
+use crate::backend::KeyboardHookManager;
+use crate::engine::Engine;
+use crate::error::KeyboardHookError;
+use crate::journal::Journal;
+use crate::runtime::Budget;
+use crate::runtime::Latency;
+use crate::runtime::Runtime;
+use crate::types::Event;
+use crate::types::FlushPolicy;
+use crate::types::HookAction;
+use crate::types::HookAction::PassOn;
 use crate::types::Key;
 use crate::types::Modifier;
-use crate::types::{Event, Modifier::*};
-use crate::windows::HookAction::{PassOn, Suppress};
-use crate::windows::KeyboardHookManager;
-use crate::windows::{HookAction, KeypressCallback};
+use crate::types::Modifier::*;
 use crate::KeyPress;
-use crate::SystemAction;
 use core::hash::Hash;
 use std::fmt::Debug;
 use std::fmt::Display;
-use std::sync::{mpsc, Arc, Condvar, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 
-const TIMEOUT_MS: u64 = 650;
+/// What the backend calls from its hook, with the raw key code of each key press.
+pub(crate) trait KeypressCallback {
+    fn handle(&mut self, key: u32, modifiers: &[Modifier]) -> HookAction;
 
-pub struct Buffers<A, T>
-where
-    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-{
-    pub key_buffer: KeyPressBuffer,
-    pub actions_on_timeout: Actions<A, T>,
-}
-
-impl<A, T> Buffers<A, T>
-where
-    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-{
-    pub fn new() -> Self {
-        Self {
-            key_buffer: KeyPressBuffer::new(),
-            actions_on_timeout: Actions::empty(),
-        }
-    }
+    /// Called on the hook thread right before the message loop stops.
+    fn stop(&mut self, policy: FlushPolicy);
 }
 
-struct SharedState<A, T>
-where
-    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-{
-    sender: mpsc::Sender<Event<A, T>>,
-    timeout_cancelled: bool,
-    quitting: bool,
-    timeout_retrigger: bool,
-    timeout_running: bool,
-    timeout_action: Option<A>,
-    buffers: Buffers<A, T>,
-}
-
-/// KeypressHandler should determine if we can handle the key press by determining the action. If
-/// the key press results in an action, we'll suppress propagating the key press event (Suppress),
-/// otherwise we'll let other hooks handle it (PassOn).
+/// Turns raw key codes from the hook into key presses for the runtime. The engine decides whether
+/// a key press is suppressed (Suppress) or left for other hooks to handle (PassOn).
 pub(crate) struct KeypressHandler<A, T>
 where
-    A: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+    A: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
 {
-    state: Arc<(Mutex<SharedState<A, T>>, Condvar)>,
-    mapping_trie: MappingTrie<A, T>,
+    runtime: Runtime<A, T>,
+    paused: Arc<AtomicBool>,
+    journal: Option<Arc<Journal>>,
 }
 
 impl<A, T> KeypressHandler<A, T>
@@ -73,144 +45,69 @@ where
     T: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
 {
     pub fn new(
-        sender: mpsc::Sender<crate::types::Event<A, T>>,
-        mapping_trie: MappingTrie<A, T>,
+        sender: mpsc::Sender<Event<A, T>>,
+        engine: Engine<A, T>,
+        paused: Arc<AtomicBool>,
+        budget: Budget,
     ) -> KeypressHandler<A, T> {
         KeypressHandler {
-            state: Arc::new((
-                Mutex::new(SharedState {
-                    sender,
-                    timeout_cancelled: false,
-                    quitting: false,
-                    timeout_retrigger: false,
-                    timeout_running: false,
-                    timeout_action: None,
-                    buffers: Buffers::new(),
-                }),
-                Condvar::new(),
-            )),
-            mapping_trie,
+            runtime: Runtime::new(engine, sender).with_budget(budget),
+            paused,
+            journal: None,
         }
     }
 
-    fn start_timeout(state_arc: Arc<(Mutex<SharedState<A, T>>, Condvar)>) {
-        let (mutex, _) = &*state_arc;
-        let state = mutex.lock().unwrap();
-
-        if state.timeout_running {
-            return;
-        }
-
-        drop(state);
-
-        let cloned_state = Arc::clone(&state_arc);
-        thread::spawn(move || loop {
-            let (mutex, condvar) = &*cloned_state;
-            let mut state = mutex.lock().unwrap();
-            state.timeout_running = true;
-            state.timeout_retrigger = false;
-            state.timeout_cancelled = false;
-
-            let (lock_result, timeout_result) = condvar
-                .wait_timeout_while(
-                    state,
-                    Duration::from_millis(TIMEOUT_MS),
-                    |s: &mut SharedState<A, T>| {
-                        !s.timeout_retrigger && !s.quitting && !s.timeout_cancelled
-                    },
-                )
-                .unwrap();
+    pub fn latency(&self) -> Arc<Latency> {
+        self.runtime.latency()
+    }
 
-            let mut state = lock_result;
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        self.runtime = self.runtime.with_journal(journal.clone());
+        self.journal = Some(journal);
+        self
+    }
 
-            if state.quitting {
-                state.timeout_running = false;
-                return;
-            }
+    fn try_handle(
+        &mut self,
+        key: u32,
+        modifiers: &[Modifier],
+        paused: bool,
+    ) -> Result<HookAction, KeyboardHookError> {
+        if paused {
+            return Ok(PassOn);
+        }
 
-            if timeout_result.timed_out() && !state.timeout_retrigger && !state.timeout_cancelled {
-                println!("Timeout!");
+        let Some(key_press) = key_press(key, modifiers) else {
+            return Ok(PassOn);
+        };
+        let hook_action = self.runtime.handle(&key_press)?;
 
-                if let Some(timeout_action) = &state.timeout_action {
-                    state
-                        .sender
-                        .send(Event::Single(timeout_action.clone()))
-                        .unwrap();
-                } else if let Some(tag) = state.buffers.actions_on_timeout.get_tag() {
-                    state
-                        .sender
-                        .send(Event::Multi(
-                            tag.clone(),
-                            state
-                                .buffers
-                                .actions_on_timeout
-                                .get_actions_on_timeout()
-                                .clone(),
-                        ))
-                        .unwrap();
-                }
+        if self.runtime.is_stopped() {
+            KeyboardHookManager::stop_windows_loop();
+        }
 
-                state.buffers.key_buffer.clear();
-                state.buffers.actions_on_timeout.clear();
-                state.timeout_action = None;
-                state.timeout_running = false;
-                break;
-            } else if state.timeout_cancelled {
-                state.buffers.key_buffer.clear();
-                state.buffers.actions_on_timeout.clear();
-                state.timeout_action = None;
-                state.timeout_running = false;
-                break;
-            }
-        });
+        Ok(hook_action)
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-pub(crate) enum KeyHandlerAction<A, T>
-where
-    A: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-{
-    Nothing, // TODO: Add reason for clarity.
-    Timeout,
-    Action(A),
-    ActionBeforeTimeout(A),
-    ActionOnTimeout(A),
-    ActionsOnTimeout(Actions<A, T>),
-    ActionsBeforeAndOnTimeout { before: A, on: Actions<A, T> },
-    StopTheHook,
-}
-
-use KeyHandlerAction::*;
-
-impl<A, T> Display for KeyHandlerAction<A, T>
-where
-    A: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-    T: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Nothing => write!(f, "Nothing"),
-            Timeout => write!(f, "Timeout"),
-            Action(action) => write!(f, "Action({})", action),
-            ActionBeforeTimeout(action) => {
-                write!(f, "ActionBeforeTimeout({})", action)
-            }
-            ActionOnTimeout(action) => {
-                write!(f, "ActionOnTimeout({})", action)
-            }
-            ActionsBeforeAndOnTimeout { before, on } => {
-                write!(
-                    f,
-                    "ActionBeforeAndOnTimeout(before: {}, on: {})",
-                    before, on
-                )
-            }
-            ActionsOnTimeout(actions) => write!(f, "ActionsOnTimeout({})", actions),
-            StopTheHook => write!(f, "StopTheHook"),
-        }
+/// The key press for a raw key code from the hook, none for a modifier key pressed on its own.
+pub(crate) fn key_press(key: u32, modifiers: &[Modifier]) -> Option<KeyPress> {
+    let modifier = if modifiers.contains(&ModAlt) && modifiers.contains(&ModShift) {
+        ModAltShift
+    } else if modifiers.contains(&ModAlt) {
+        ModAlt
+    } else if modifiers.contains(&ModShift) {
+        ModShift
+    } else {
+        NoMod
+    };
+
+    // We don't care about Alt, Ctrl, Shift, Win alone. We only use these as modifiers.
+    if [91, 92, 93, 160, 161, 162, 163, 164, 165].contains(&key) {
+        return None;
     }
+
+    Some(KeyPress::Mod(Key::from_u8(key as u8), modifier))
 }
 
 impl<A, T> KeypressCallback for KeypressHandler<A, T>
@@ -219,150 +116,59 @@ where
     T: 'static + PartialEq + Eq + Clone + Debug + Send + Sync + Display + Hash,
 {
     fn handle(&mut self, key: u32, modifiers: &[Modifier]) -> HookAction {
-        let modifier = if modifiers.contains(&ModAlt) && modifiers.contains(&ModShift) {
-            ModAltShift
-        } else if modifiers.contains(&ModAlt) {
-            ModAlt
-        } else if modifiers.contains(&ModShift) {
-            ModShift
-        } else {
-            NoMod
-        };
+        let paused = self.paused.load(Ordering::SeqCst);
 
-        // We don't care about Alt, Ctrl, Shift, Win alone. We only use these as modifiers.
-        if [91, 92, 93, 160, 161, 162, 163, 164, 165].contains(&key) {
-            return PassOn;
+        if let Some(journal) = &self.journal {
+            journal.key(key, modifiers, paused);
         }
 
-        let key_press = KeyPress::Mod(Key::from_u8(key as u8), modifier);
-        let (mutex, condvar) = &*self.state;
-
-        let handler_action = {
-            let mut state = mutex.lock().unwrap();
-            find_mapping(&key_press, &self.mapping_trie, &mut state.buffers)
-        };
-
-        match handler_action {
-            Nothing => {
-                println!("Nothing");
-                let mut state = mutex.lock().unwrap();
-                state.timeout_action = None;
-                state.buffers.key_buffer.clear();
-                state.buffers.actions_on_timeout.clear();
-            }
-            Timeout => {
-                let mut state = mutex.lock().unwrap();
-
-                if state.timeout_running {
-                    state.timeout_retrigger = true;
-                    drop(state);
-                    condvar.notify_one();
-                } else {
-                    drop(state);
-                    Self::start_timeout(Arc::clone(&self.state));
-                }
-
-                return Suppress;
-            }
-            Action(ref action) => {
-                let mut state = mutex.lock().unwrap();
-                state.timeout_cancelled = true;
-                state.timeout_action = None;
-                state.buffers.key_buffer.clear();
-                state.buffers.actions_on_timeout.clear();
-                state.sender.send(Event::Single(action.clone())).unwrap();
+        let hook_action = self
+            .try_handle(key, modifiers, paused)
+            .unwrap_or_else(|error| {
+                tracing::warn!(%error, "Passing the key on");
+                PassOn
+            });
 
-                condvar.notify_one();
-                drop(state);
-
-                return Suppress;
-            }
-            ActionBeforeTimeout(ref action) => {
-                let mut state = mutex.lock().unwrap();
-                state.sender.send(Event::Single(action.clone())).unwrap();
-
-                state.timeout_action = None;
-
-                if state.timeout_running {
-                    state.timeout_retrigger = true;
-                    drop(state);
-                    condvar.notify_one();
-                } else {
-                    drop(state);
-                    Self::start_timeout(Arc::clone(&self.state));
-                }
-
-                return Suppress;
-            }
-            ActionOnTimeout(ref action) => {
-                let mut state = mutex.lock().unwrap();
-                state.timeout_action = Some(action.clone());
-
-                if state.timeout_running {
-                    state.timeout_retrigger = true;
-                    drop(state);
-                    condvar.notify_one();
-                } else {
-                    drop(state);
-                    Self::start_timeout(Arc::clone(&self.state));
-                }
-
-                return Suppress;
-            }
-            ActionsOnTimeout(_) => {
-                let mut state = mutex.lock().unwrap();
-                state.timeout_action = None;
-
-                if state.timeout_running {
-                    state.timeout_retrigger = true;
-                    drop(state);
-                    condvar.notify_one();
-                } else {
-                    drop(state);
-                    Self::start_timeout(Arc::clone(&self.state));
-                }
-
-                return Suppress;
-            }
-            ActionsBeforeAndOnTimeout { ref before, .. } => {
-                let mut state = mutex.lock().unwrap();
-                state.sender.send(Event::Single(before.clone())).unwrap();
-                state.timeout_action = None;
-
-                if state.timeout_running {
-                    state.timeout_retrigger = true;
-                    drop(state);
-                    condvar.notify_one();
-                } else {
-                    drop(state);
-                    Self::start_timeout(Arc::clone(&self.state));
-                }
+        if let Some(journal) = &self.journal {
+            journal.decision(hook_action);
+        }
 
-                return Suppress;
-            }
-            StopTheHook => {
-                let mut state = mutex.lock().unwrap();
-                state.quitting = true;
-                KeyboardHookManager::stop_windows_loop();
-                state
-                    .sender
-                    .send(Event::System(SystemAction::KeyboardUnhooked))
-                    .unwrap();
+        hook_action
+    }
 
-                return Suppress;
-            }
+    fn stop(&mut self, policy: FlushPolicy) {
+        if let Some(journal) = &self.journal {
+            journal.stop(policy);
         }
 
-        let mut state = mutex.lock().unwrap();
-        state.buffers.actions_on_timeout.clear();
-
-        if state.timeout_running {
-            println!("Resetting.");
-            state.timeout_cancelled = true;
-            drop(state);
-            condvar.notify_one();
+        if let Err(error) = self.runtime.stop(policy) {
+            tracing::warn!(%error, "Failed to stop cleanly");
         }
+    }
+}
 
-        PassOn
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+
+    #[test]
+    fn should_pass_keys_on_when_the_handler_is_disconnected() {
+        // Given
+        let (engine, _) = Engine::new(keymap! { "w" => "Kenny" });
+        let (tx, rx) = mpsc::channel::<Event<&str, &str>>();
+        let mut handler = KeypressHandler::new(
+            tx,
+            engine,
+            Arc::new(AtomicBool::new(false)),
+            Budget::default(),
+        );
+        drop(rx);
+
+        // When
+        let action = handler.handle(b'W' as u32, &[]);
+
+        // Then
+        assert_eq!(action, PassOn);
     }
 }
@@ -1,30 +1,83 @@
+extern crate self as keyboard_hook;
+
 pub mod action_handler;
+pub mod builder;
+pub mod builtin;
+pub mod cheat_sheet;
+pub mod config;
+pub mod diff;
+pub mod engine;
+pub mod error;
+pub mod export;
+pub mod fragment;
+mod handle;
+pub mod journal;
+// Only a backend calls into the key handler, and only Windows has one.
+#[cfg_attr(not(windows), allow(dead_code))]
 mod key_handler;
-mod keypress_buffer;
 pub mod macros;
 mod mapping_manager;
-mod mapping_trie;
+pub mod mapping_trie;
+#[cfg(test)]
+mod reference_matcher;
+pub mod registry;
+pub mod runtime;
+pub mod static_trie;
+#[cfg(feature = "stream")]
+pub mod stream;
+pub mod testing;
 pub mod types;
+#[cfg(not(windows))]
+#[allow(dead_code)]
+mod unsupported;
+#[cfg(windows)]
 mod windows;
 
 pub use crate::action_handler::ActionHandler;
+use crate::engine::Engine;
+pub use crate::error::KeyboardHookError;
+use crate::fragment::Mappings;
+pub use crate::handle::HookHandle;
+use crate::journal::Journal;
 use crate::key_handler::KeypressHandler;
 use crate::mapping_trie::MappingTrie;
+pub use crate::registry::ActionRegistry;
+use crate::runtime::Budget;
+use crate::static_trie::StaticMappingTrie;
 use crate::types::*;
-use crate::windows::KeyboardHookManager;
+#[cfg(not(windows))]
+use crate::unsupported as backend;
+#[cfg(windows)]
+use crate::windows as backend;
+use backend::KeyboardHookManager;
 use core::hash::Hash;
+pub use keyboard_hook_macros::keymap;
+pub use keyboard_hook_macros::static_keymap;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
 use std::thread;
 
+enum MappingSource<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Mappings(Mappings<A, T>),
+    Static(&'static StaticMappingTrie<A, T>),
+}
+
 pub struct KeyboardHook<A, T>
 where
-    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
-    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
 {
     handler: Arc<Box<dyn ActionHandler<A, T> + Send + Sync>>,
-    mappings: Arc<Vec<Vec<Mapping<A, T>>>>,
+    mappings: Arc<MappingSource<A, T>>,
+    log_unmapped_keys: bool,
+    budget: Budget,
+    journal: Option<Arc<Journal>>,
 }
 
 impl<A, T> KeyboardHook<A, T>
@@ -33,38 +86,141 @@ where
     T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
 {
     pub fn new(
-        mappings: Vec<Vec<Mapping<A, T>>>,
+        mappings: impl Into<Mappings<A, T>>,
+        handler: Box<dyn ActionHandler<A, T> + Send + Sync>,
+    ) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            mappings: Arc::new(MappingSource::Mappings(mappings.into())),
+            log_unmapped_keys: false,
+            budget: Budget::default(),
+            journal: None,
+        }
+    }
+
+    /// Delivers events as a `Stream` instead of to an `ActionHandler`. `hook` still blocks, so run
+    /// it on its own thread, e.g. with `tokio::task::spawn_blocking`.
+    #[cfg(feature = "stream")]
+    pub fn with_stream(mappings: impl Into<Mappings<A, T>>) -> (Self, stream::EventStream<A, T>) {
+        let (handler, events) = stream::channel();
+
+        (Self::new(mappings, Box::new(handler)), events)
+    }
+
+    /// Uses mappings laid out at compile time with `static_keymap!`.
+    pub fn from_static(
+        trie: &'static StaticMappingTrie<A, T>,
         handler: Box<dyn ActionHandler<A, T> + Send + Sync>,
     ) -> Self {
         Self {
             handler: Arc::new(handler),
-            mappings: Arc::new(mappings),
+            mappings: Arc::new(MappingSource::Static(trie)),
+            log_unmapped_keys: false,
+            budget: Budget::default(),
+            journal: None,
         }
     }
 
-    pub fn hook(&self) -> Result<(), &str> {
+    /// Includes keys that aren't part of any mapping in the `tracing` output. Off by default, so
+    /// the log doesn't record what's being typed.
+    pub fn log_unmapped_keys(mut self, enabled: bool) -> Self {
+        self.log_unmapped_keys = enabled;
+        self
+    }
+
+    /// How long handling a key press may take before a `SystemAction::OverBudget` event is sent,
+    /// and how long to wait for the timer thread before passing a key on.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Records key presses, decisions and events to the journal, to be replayed with
+    /// `journal::replay`. Like `log_unmapped_keys`, this records everything typed.
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Hooks the keyboard and blocks until it's unhooked.
+    pub fn hook(&self) -> Result<(), KeyboardHookError> {
+        self.start()?.join()
+    }
+
+    /// Hooks the keyboard on a background thread. Returns once the hook is installed.
+    pub fn start(&self) -> Result<HookHandle, KeyboardHookError> {
         let (tx, rx) = mpsc::channel::<Event<A, T>>();
 
         let handler = self.handler.clone();
-        let consumer_handle = thread::spawn(move || {
+        let consumer = thread::spawn(move || {
             handler.handle(rx);
         });
 
         let mappings = self.mappings.clone();
+        let log_unmapped_keys = self.log_unmapped_keys;
+        let budget = self.budget;
+        let journal = self.journal.clone();
+        let paused = Arc::new(AtomicBool::new(false));
+        let running = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
 
-        let producer_handle = thread::spawn(move || {
-            let mut manager = KeyboardHookManager::new()?;
-            let handler = Box::new(KeypressHandler::new(
-                tx.clone(),
-                MappingTrie::from_mappings(&mappings),
-            ));
+        let producer = {
+            let paused = paused.clone();
+            let running = running.clone();
 
-            manager.hook(tx.clone(), handler)
-        });
+            thread::spawn(move || {
+                let mut manager = KeyboardHookManager::new()?;
+                let engine = match &*mappings {
+                    MappingSource::Mappings(mappings) => {
+                        let (trie, conflicts) = MappingTrie::new(mappings);
+
+                        for conflict in conflicts {
+                            tracing::warn!(%conflict, "Conflicting mapping left out");
+                        }
+
+                        Engine::from_lookup(Box::new(trie))
+                    }
+                    MappingSource::Static(trie) => Engine::from_static(trie),
+                };
+                let engine = engine.log_unmapped_keys(log_unmapped_keys);
+                let handler = KeypressHandler::new(tx.clone(), engine, paused, budget);
+                let handler = Box::new(match journal {
+                    Some(journal) => handler.with_journal(journal),
+                    None => handler,
+                });
+                let latency = handler.latency();
 
-        let _ = producer_handle.join().unwrap();
-        consumer_handle.join().unwrap();
+                let result = manager.hook(tx.clone(), handler, |thread| {
+                    running.store(true, Ordering::SeqCst);
+                    let _ = ready_tx.send((thread, latency));
+                });
 
-        Ok(())
+                running.store(false, Ordering::SeqCst);
+                result
+            })
+        };
+
+        match ready_rx.recv() {
+            Ok((thread, latency)) => Ok(HookHandle {
+                thread,
+                latency,
+                paused,
+                running,
+                producer,
+                consumer,
+            }),
+            Err(_) => {
+                let result = producer.join().unwrap_or_else(|_| {
+                    Err(KeyboardHookError::Backend(
+                        "the hook thread panicked".into(),
+                    ))
+                });
+                let _ = consumer.join();
+
+                Err(result.err().unwrap_or_else(|| {
+                    KeyboardHookError::Backend("the hook stopped before it was installed".into())
+                }))
+            }
+        }
     }
 }
@@ -1,14 +1,12 @@
-use crate::key_handler::Buffers;
-use crate::key_handler::KeyHandlerAction;
-use crate::mapping_trie::MappingTrie;
+use crate::engine::Buffers;
+use crate::engine::KeyHandlerAction;
+use crate::mapping_trie::MappingLookup;
 use crate::Behaviour;
 use crate::KeyPress;
-use crate::Mapping;
 use core::hash::Hash;
 use std::fmt::Debug;
 use std::fmt::Display;
 use KeyHandlerAction::*;
-use Mapping::*;
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Actions<A, T>
@@ -40,10 +38,6 @@ where
         }
     }
 
-    pub fn get_actions_on_timeout(&self) -> &Vec<A> {
-        &self.actions
-    }
-
     pub fn push(&mut self, action: A, tag: T) {
         self.actions.push(action);
         self.tag = Some(tag);
@@ -81,24 +75,31 @@ where
     }
 }
 
-pub fn find_mapping<A, T>(
+pub fn find_mapping<A, T, L>(
     key_press: &KeyPress,
-    trie: &MappingTrie<A, T>,
+    trie: &L,
     buffers: &mut Buffers<A, T>,
-) -> KeyHandlerAction<A, T>
+) -> KeyHandlerAction<A>
 where
     A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
     T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    L: MappingLookup<A, T> + ?Sized,
 {
-    let mapping = trie.find_mapping(key_press, buffers.key_buffer.get_keypresses());
+    let found = trie.advance(buffers.cursor, key_press);
 
-    if let Some(m) = mapping {
-        buffers.key_buffer.push(key_press.clone());
-        let action = to_handler_action(m, key_press, &mut buffers.actions_on_timeout);
+    if let Some((cursor, behaviour, tag)) = found {
+        tracing::trace!(
+            key = %key_press,
+            node = if tag.is_some() { "choice" } else { "single" },
+            %behaviour,
+            "Found mapping"
+        );
+
+        buffers.cursor = cursor;
+        let action = to_handler_action(behaviour, tag, &mut buffers.actions_on_timeout);
 
         if let Action(_) = action {
-            buffers.key_buffer.clear();
-            buffers.actions_on_timeout.clear();
+            buffers.clear();
         }
 
         action
@@ -107,17 +108,19 @@ where
     }
 }
 
+/// Turns the behaviour of a pressed key into an action. Behaviours of a choice (tagged) aggregate
+/// their actions on timeout, which are left in `actions` rather than copied into the result.
 pub fn to_handler_action<A, T>(
-    mapping: &Mapping<A, T>,
-    key: &KeyPress,
+    behaviour: &Behaviour<A>,
+    tag: Option<&T>,
     actions: &mut Actions<A, T>,
-) -> KeyHandlerAction<A, T>
+) -> KeyHandlerAction<A>
 where
     A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
     T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
 {
-    match mapping {
-        Single(behaviour) => match behaviour {
+    match tag {
+        None => match behaviour {
             Behaviour::Timeout(_) => Timeout,
             Behaviour::Action(_, action_type) => Action(action_type.clone()),
             Behaviour::ActionOnTimeout(_, action) => {
@@ -126,34 +129,26 @@ where
             }
             Behaviour::Shutdown(_) => StopTheHook,
         },
-        Choice(behaviours, tag) => {
-            match behaviours.get_mapping(key) {
-                Some(mapping) => match (mapping, actions.is_empty()) {
-                    (Behaviour::Timeout(_), true) => Timeout,
-                    (Behaviour::Timeout(_), false) => ActionsOnTimeout(actions.clone()),
-                    (Behaviour::Action(_, action), true) => ActionBeforeTimeout(action.clone()),
-                    (Behaviour::Action(_, action), false) => ActionsBeforeAndOnTimeout {
-                        before: action.clone(),
-                        on: actions.clone(),
-                    },
-                    (Behaviour::ActionOnTimeout(_, action), true) => {
-                        actions.push(action.clone(), tag.clone());
-                        ActionsOnTimeout(actions.clone())
-                    }
-                    (Behaviour::ActionOnTimeout(_, action), false) => {
-                        if actions.get_tag().is_none() {
-                            actions.push(action.clone(), tag.clone());
-                        } else {
-                            actions.push_action(action.clone());
-                        }
-
-                        ActionsOnTimeout(actions.clone())
-                    }
-                    (Behaviour::Shutdown(_), _) => StopTheHook,
-                },
-                None => Nothing, // Should never happen.
+        Some(tag) => match (behaviour, actions.is_empty()) {
+            (Behaviour::Timeout(_), true) => Timeout,
+            (Behaviour::Timeout(_), false) => ActionsOnTimeout,
+            (Behaviour::Action(_, action), true) => ActionBeforeTimeout(action.clone()),
+            (Behaviour::Action(_, action), false) => ActionsBeforeAndOnTimeout(action.clone()),
+            (Behaviour::ActionOnTimeout(_, action), true) => {
+                actions.push(action.clone(), tag.clone());
+                ActionsOnTimeout
             }
-        }
+            (Behaviour::ActionOnTimeout(_, action), false) => {
+                if actions.get_tag().is_none() {
+                    actions.push(action.clone(), tag.clone());
+                } else {
+                    actions.push_action(action.clone());
+                }
+
+                ActionsOnTimeout
+            }
+            (Behaviour::Shutdown(_), _) => StopTheHook,
+        },
     }
 }
 
@@ -161,26 +156,17 @@ where
 mod tests {
     use super::*;
     use crate::types::Key::*;
-    use crate::types::Modifier::ModAlt;
+    use crate::types::Mapping;
+    use crate::types::Mapping::Single;
     use crate::*;
-    use key_handler::Buffers;
-    use key_handler::KeyHandlerAction;
+    use engine::Buffers;
+    use engine::KeyHandlerAction;
     use mapping_manager::find_mapping;
     use rstest::rstest;
 
-    macro_rules! m {
-        ( [ $( [ $( $x:expr ),* $(,)? ] ),* $(,)? ] ) => {
-            vec![
-                $(
-                    vec![$($x),*]
-                ),*
-            ]
-        }
-    }
-
     macro_rules! t_actions {
         ([$($actions:expr),* $(,)?], $tag:expr) => {
-            $crate::key_handler::KeyHandlerAction::ActionsOnTimeout(
+            Step::ActionsOnTimeout(
                 $crate::mapping_manager::Actions::from(vec![$($actions),*], $tag),
             )
         };
@@ -188,7 +174,7 @@ mod tests {
 
     macro_rules! actions {
         ($action_before:expr, [$($actions:expr),* $(,)?], $tag:expr) => {
-            $crate::key_handler::KeyHandlerAction::ActionsBeforeAndOnTimeout{
+            Step::ActionsBeforeAndOnTimeout{
                 before: $action_before,
                 on: $crate::mapping_manager::Actions::from(vec![$($actions),*], $tag),
             }
@@ -247,115 +233,121 @@ mod tests {
         }
     }
 
+    /// A handler action along with the actions aggregated in the buffers by then.
+    #[derive(PartialEq, Eq, Debug)]
+    enum Step {
+        Nothing,
+        Timeout,
+        Action(TestAction),
+        ActionBeforeTimeout(TestAction),
+        ActionOnTimeout(TestAction),
+        ActionsOnTimeout(Actions<TestAction, TestTag>),
+        ActionsBeforeAndOnTimeout {
+            before: TestAction,
+            on: Actions<TestAction, TestTag>,
+        },
+        StopTheHook,
+    }
+
+    use Step::{Action, ActionBeforeTimeout, ActionOnTimeout, Nothing, StopTheHook, Timeout};
+
+    fn feed(
+        mappings: Vec<Vec<Mapping<TestAction, TestTag>>>,
+        keypresses: &[KeyPress],
+    ) -> Vec<Step> {
+        let (trie, _) = MappingTrie::new(&mappings.into());
+        let mut buffers = Buffers::new();
+
+        keypresses
+            .iter()
+            .map(|key| {
+                let action = find_mapping(key, &trie, &mut buffers);
+                let on = || buffers.actions_on_timeout.clone();
+
+                match action {
+                    KeyHandlerAction::Nothing => Nothing,
+                    KeyHandlerAction::Timeout => Timeout,
+                    KeyHandlerAction::Action(action) => Action(action),
+                    KeyHandlerAction::ActionBeforeTimeout(action) => ActionBeforeTimeout(action),
+                    KeyHandlerAction::ActionOnTimeout(action) => ActionOnTimeout(action),
+                    KeyHandlerAction::ActionsOnTimeout => Step::ActionsOnTimeout(on()),
+                    KeyHandlerAction::ActionsBeforeAndOnTimeout(before) => {
+                        Step::ActionsBeforeAndOnTimeout { before, on: on() }
+                    }
+                    KeyHandlerAction::StopTheHook => StopTheHook,
+                }
+            })
+            .collect()
+    }
+
     #[rstest]
     // Should invoke a timeout for a key without a modifier.
-    #[case(m!([[t!(KeyA)]]), &[key!(KeyA)], &[Timeout])]
+    #[case(keymap! { "a" => timeout }, &[key!(KeyA)], &[Timeout])]
     // Should do nothing if the key doesn't match.
-    #[case(m!([[t!(KeyA)]]), &[key!(KeyX)], &[Nothing])]
+    #[case(keymap! { "a" => timeout }, &[key!(KeyX)], &[Nothing])]
     // Should invoke a timeout for a key with a modifier.
-    #[case(m!([[t!(KeyA, ModAlt)]]), &[alt!(KeyA)], &[Timeout])]
+    #[case(keymap! { "<A-a>" => timeout }, &[alt!(KeyA)], &[Timeout])]
     // Should do nothing if the modifier doesn't match.
-    #[case(m!([[t!(KeyA, ModAlt)]]), &[key!(KeyA)], &[Nothing])]
+    #[case(keymap! { "<A-a>" => timeout }, &[key!(KeyA)], &[Nothing])]
     // Should invoke an immediate action for a key without a modifier.
-    #[case(m!([[a!(KeyA, VolUp)]]), &[key!(KeyA)], &[Action(VolUp)])]
+    #[case(keymap! { "a" => VolUp }, &[key!(KeyA)], &[Action(VolUp)])]
     // Should invoke an immediate action for a key with a modifier.
-    #[case(m!([[a!(KeyA, ModAlt, VolUp)]]), &[alt!(KeyA)], &[Action(VolUp)])]
+    #[case(keymap! { "<A-a>" => VolUp }, &[alt!(KeyA)], &[Action(VolUp)])]
     // Should invoke an action on the timeout for a key without a modifier.
-    #[case(m!([[aot!(KeyA, VolUp)]]), &[key!(KeyA)], &[ActionOnTimeout(VolUp)])]
+    #[case(keymap! { "a" => VolUp on timeout }, &[key!(KeyA)], &[ActionOnTimeout(VolUp)])]
     // Should invoke an action on the timeout for a key with a modifier.
-    #[case(m!([[aot!(KeyA, ModAlt, VolUp)]]), &[alt!(KeyA)], &[ActionOnTimeout(VolUp)])]
+    #[case(keymap! { "<A-a>" => VolUp on timeout }, &[alt!(KeyA)], &[ActionOnTimeout(VolUp)])]
     // Should do nothing if the first key doesn't match the first one in the mapping sequence.
-    #[case(m!([[t!(KeyA), a!(KeyB, VolUp)]]), &[key!(KeyB)], &[Nothing])]
+    #[case(keymap! { "a b" => VolUp }, &[key!(KeyB)], &[Nothing])]
     // Should invoke an immediate action if the sequence of keys match and the last key is mapped
     // to an action.
-    #[case(m!([[t!(KeyA), a!(KeyB, VolUp)]]), &[key!(KeyA), key!(KeyB)], &[Timeout, Action(VolUp)])]
+    #[case(keymap! { "a b" => VolUp }, &[key!(KeyA), key!(KeyB)], &[Timeout, Action(VolUp)])]
     // Should invoke the first action and ignore the second, because action resets the sequence.
-    #[case(m!([[a!(KeyA, VolUp), a!(KeyB, VolDown)]]), &[key!(KeyA), key!(KeyB)], &[Action(VolUp), Nothing])]
+    #[case(vec![vec![Single(Behaviour::nomod_a(KeyA, VolUp)), Single(Behaviour::nomod_a(KeyB, VolDown))]], &[key!(KeyA), key!(KeyB)], &[Action(VolUp), Nothing])]
     // Should aggregate actions on timeout.
-    #[case(m!([[t!(KeyA), c!([key_aot!(Key1, Chan1), key_aot!(Key2, Chan2)], TogChans)]]), &[key!(KeyA), key!(Key1), key!(Key2)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans)])]
+    #[case(keymap! { "a [12]*" as TogChans => { 1 => Chan1 on timeout, 2 => Chan2 on timeout } }, &[key!(KeyA), key!(Key1), key!(Key2)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans)])]
     // Should aggregate actions on timeout (different keypress order).
-    #[case(m!([[t!(KeyA), c!([key_aot!(Key1, Chan1), key_aot!(Key2, Chan2)], TogChans)]]), &[key!(KeyA), key!(Key2), key!(Key1)], &[Timeout, t_actions!([Chan2], TogChans), t_actions!([Chan2, Chan1], TogChans)])]
+    #[case(keymap! { "a [12]*" as TogChans => { 1 => Chan1 on timeout, 2 => Chan2 on timeout } }, &[key!(KeyA), key!(Key2), key!(Key1)], &[Timeout, t_actions!([Chan2], TogChans), t_actions!([Chan2, Chan1], TogChans)])]
     // Should include repeated actions in aggregate actions on timeout.
-    #[case(m!([[t!(KeyA), c!([key_aot!(Key1, Chan1), key_aot!(Key2, Chan2)], TogChans)]]), &[key!(KeyA), key!(Key1), key!(Key2), key!(Key1)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans), t_actions!([Chan1, Chan2, Chan1], TogChans)])]
+    #[case(keymap! { "a [12]*" as TogChans => { 1 => Chan1 on timeout, 2 => Chan2 on timeout } }, &[key!(KeyA), key!(Key1), key!(Key2), key!(Key1)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans), t_actions!([Chan1, Chan2, Chan1], TogChans)])]
     // Should include timeout keys in aggregates.
-    #[case(m!([[t!(KeyA), c!([key_t!(Key1), key_aot!(Key2, Chan2)], TogChans)]]), &[key!(KeyA), key!(Key1), key!(Key2), key!(Key1)], &[Timeout, Timeout, t_actions!([Chan2], TogChans), t_actions!([Chan2], TogChans)])]
+    #[case(keymap! { "a [12]*" as TogChans => { 1 => timeout, 2 => Chan2 on timeout } }, &[key!(KeyA), key!(Key1), key!(Key2), key!(Key1)], &[Timeout, Timeout, t_actions!([Chan2], TogChans), t_actions!([Chan2], TogChans)])]
     // Should invoke an immediate action and then aggregated actions on timeout.
-    #[case(m!([[t!(KeyA), c!([key_aot!(Key1, Chan1), key_aot!(Key2, Chan2), key_a!(Key3, Chan3)], TogChans)]]), &[key!(KeyA), key!(Key1), key!(Key2), key!(Key3)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans), actions!(Chan3, [Chan1, Chan2], TogChans)])]
+    #[case(keymap! { "a [1-3]*" as TogChans => { 1 => Chan1 on timeout, 2 => Chan2 on timeout, 3 => Chan3 } }, &[key!(KeyA), key!(Key1), key!(Key2), key!(Key3)], &[Timeout, t_actions!([Chan1], TogChans), t_actions!([Chan1, Chan2], TogChans), actions!(Chan3, [Chan1, Chan2], TogChans)])]
     fn should_match_keys_to_mappings(
         #[case] mappings: Vec<Vec<Mapping<TestAction, TestTag>>>,
         #[case] keypresses: &[KeyPress],
-        #[case] expected: &[KeyHandlerAction<TestAction, TestTag>],
+        #[case] expected: &[Step],
     ) {
-        // Given
-        let trie = MappingTrie::from_mappings(&mappings);
-        let mut result = vec![];
-        let mut buffers = Buffers::new();
-
         // When
-        for key in keypresses {
-            result.push(find_mapping(key, &trie, &mut buffers));
-        }
+        let result = feed(mappings, keypresses);
 
-        // Thre asd
+        // Then
         assert_eq!(result, expected)
     }
 
     fn demo_mappings() -> Vec<Vec<Mapping<TestAction, TestTag>>> {
-        vec![
-            // Alt+A -> E -> X -> I -> T
-            vec![
-                t!(KeyA, ModAlt),
-                t!(KeyE),
-                t!(KeyX),
-                t!(KeyI),
-                shutdown!(KeyT), // Unhook the keyboard and exit.
-            ],
-            // Alt+A -> Q
-            vec![
-                t!(KeyA, ModAlt),
-                aot!(KeyQ, Princess), // Action on timeout.
-            ],
-            // Alt+A -> W
-            vec![
-                t!(KeyA, ModAlt),
-                a!(KeyW, Kenny), // Immediate action.
-            ],
-            // Alt+A -> S -> 2 -> [12345]*
-            vec![
-                t!(KeyA, ModAlt),
-                t!(KeyS),
-                aot!(Key2, UseStrip2),
-                c!(
-                    [
-                        key_aot!(Key1, Chan1),
-                        key_aot!(Key2, Chan2),
-                        key_aot!(Key3, Chan3),
-                        key_aot!(Key4, Chan4),
-                        key_aot!(Key5, Chan5)
-                    ],
-                    TogChans
-                ),
-            ],
-            // Alt+A -> [12345]*
-            vec![
-                t!(KeyA, ModAlt),
-                c!(
-                    [
-                        key_aot!(Key1, Chan1),
-                        key_aot!(Key2, Chan2),
-                        key_aot!(Key3, Chan3),
-                        key_aot!(Key4, Chan4),
-                        key_aot!(Key5, Chan5)
-                    ],
-                    TogChans
-                ),
-            ],
-            // Alt+A -> [JK]*
-            vec![
-                t!(KeyA, ModAlt),
-                c!([key_a!(KeyJ, VolDown), key_a!(KeyK, VolUp)], Volume),
-            ],
-        ]
+        keymap! {
+            "<A-a> e x i t" => shutdown, // Unhook the keyboard and exit.
+            "<A-a> q" => Princess on timeout,
+            "<A-a> w" => Kenny,
+            "<A-a> s 2" => UseStrip2 on timeout,
+            "<A-a> s 2 [1-5]*" as TogChans => {
+                1 => Chan1 on timeout,
+                2 => Chan2 on timeout,
+                3 => Chan3 on timeout,
+                4 => Chan4 on timeout,
+                5 => Chan5 on timeout,
+            },
+            "<A-a> [1-5]*" as TogChans => {
+                1 => Chan1 on timeout,
+                2 => Chan2 on timeout,
+                3 => Chan3 on timeout,
+                4 => Chan4 on timeout,
+                5 => Chan5 on timeout,
+            },
+            "<A-a> [jk]*" as Volume => { j => VolDown, k => VolUp },
+        }
     }
 
     #[rstest]
@@ -372,19 +364,12 @@ mod tests {
     fn should_validate_demo_mappings(
         #[case] mappings: Vec<Vec<Mapping<TestAction, TestTag>>>,
         #[case] keypresses: &[KeyPress],
-        #[case] expected: &[KeyHandlerAction<TestAction, TestTag>],
+        #[case] expected: &[Step],
     ) {
-        // Given
-        let trie = MappingTrie::from_mappings(&mappings);
-        let mut result = vec![];
-        let mut buffers = Buffers::new();
-
         // When
-        for key in keypresses {
-            result.push(find_mapping(key, &trie, &mut buffers));
-        }
+        let result = feed(mappings, keypresses);
 
-        // Thre asd
+        // Then
         assert_eq!(result, expected)
     }
 }
@@ -1,173 +1,623 @@
 use core::hash::Hash;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::sync::Arc;
 
-use crate::types::Behaviours;
+use crate::fragment::Mappings;
+use crate::types::Behaviour;
+use crate::types::Conflict;
+use crate::types::MountPoint;
 use crate::types::{Mapping, Mapping::Choice, Mapping::Single};
 use crate::KeyPress;
 
-type KeyHashMap<A, T> = HashMap<KeyPress, MappingTrieNode<A, T>>;
+/// A position in a mapping trie, reached by the keys pressed since the last reset.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Cursor(pub(crate) usize);
 
-#[derive(Debug)]
-enum MappingTrieNode<A, T>
+impl Cursor {
+    pub fn root() -> Self {
+        Self(0)
+    }
+}
+
+/// A compiled set of mappings the key handler looks key presses up in.
+pub trait MappingLookup<A, T>
 where
     A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
     T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
 {
-    Root(KeyHashMap<A, T>),
-    OneOff(Mapping<A, T>, KeyHashMap<A, T>),
-    Repeatable(Mapping<A, T>, HashSet<KeyPress>, KeyHashMap<A, T>),
+    /// Moves the cursor past the pressed key. Returns where it ended up along with the key's
+    /// behaviour and the tag of the choice it belongs to.
+    fn advance(
+        &self,
+        cursor: Cursor,
+        key: &KeyPress,
+    ) -> Option<(Cursor, &Behaviour<A>, Option<&T>)>;
+
+    /// Finds the behaviour of the key pressed after the keys in the buffer, along with the tag of
+    /// the choice it belongs to.
+    fn find_behaviour(
+        &self,
+        key: &KeyPress,
+        buffer: &[KeyPress],
+    ) -> Option<(&Behaviour<A>, Option<&T>)> {
+        let cursor = buffer
+            .iter()
+            .try_fold(Cursor::root(), |cursor, key_press| {
+                self.advance(cursor, key_press).map(|(cursor, _, _)| cursor)
+            })?;
+
+        self.advance(cursor, key)
+            .map(|(_, behaviour, tag)| (behaviour, tag))
+    }
 }
 
-impl<A, T> Display for MappingTrieNode<A, T>
+impl<A, T, L> MappingLookup<A, T> for &L
 where
-    A: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
-    T: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    L: MappingLookup<A, T> + ?Sized,
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn advance(
+        &self,
+        cursor: Cursor,
+        key: &KeyPress,
+    ) -> Option<(Cursor, &Behaviour<A>, Option<&T>)> {
+        (**self).advance(cursor, key)
+    }
+}
+
+/// Children of a node sorted by key press, each pointing at a node of the arena.
+type Edges = Vec<(KeyPress, u32)>;
+
+/// A node of the trie, referring to its mapping by its index among the interned mappings.
+#[derive(Debug)]
+enum MappingTrieNode {
+    Root(Edges),
+    OneOff(u32, Edges),
+    Repeatable(u32, Edges),
+}
+
+impl MappingTrieNode {
+    fn edges(&self) -> &Edges {
+        match self {
+            Root(edges) | OneOff(_, edges) | Repeatable(_, edges) => edges,
+        }
+    }
+
+    fn edges_mut(&mut self) -> &mut Edges {
+        match self {
+            Root(edges) | OneOff(_, edges) | Repeatable(_, edges) => edges,
+        }
+    }
+
+    fn mapping(&self) -> Option<u32> {
         match self {
-            Root(_) => write!(f, "Root"),
-            OneOff(mapping, _) => write!(f, "OneOff({})", mapping),
-            Repeatable(mapping, _, _) => write!(f, "Repeatable({})", mapping),
+            Root(_) => None,
+            OneOff(mapping, _) | Repeatable(mapping, _) => Some(*mapping),
         }
     }
+
+    fn child(&self, key: &KeyPress) -> Option<usize> {
+        let edges = self.edges();
+
+        edges
+            .binary_search_by(|(k, _)| k.cmp(key))
+            .ok()
+            .map(|i| edges[i].1 as usize)
+    }
 }
 
 use MappingTrieNode::*;
 
-pub(crate) struct MappingTrie<A, T>
+struct MapContext {
+    mount: Option<MountPoint>,
+    conflicts: Vec<Conflict>,
+}
+
+impl MapContext {
+    fn conflict(&mut self, path: &[KeyPress], reason: String) {
+        self.conflicts.push(Conflict {
+            path: path.to_vec(),
+            reason,
+            mount: self.mount.clone(),
+        });
+    }
+}
+
+/// A mapping stored once however many nodes refer to it, along with the sorted keys of a choice.
+#[derive(Debug)]
+struct Interned<A, T>
 where
-    A: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
-    T: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
 {
-    root: MappingTrieNode<A, T>,
+    mapping: Mapping<A, T>,
+    choice_keys: Box<[KeyPress]>,
 }
 
-impl<A, T> MappingTrie<A, T>
+#[derive(Debug)]
+struct Arena<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    nodes: Vec<MappingTrieNode>,
+    mappings: Vec<Interned<A, T>>,
+}
+
+impl<A, T> Arena<A, T>
 where
     A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
     T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
 {
-    fn all_keys_available(keys: &KeyHashMap<A, T>, key_presses: &Behaviours<A>) -> bool {
-        for key_mapping in &key_presses.0 {
-            if keys.contains_key(&key_mapping.get_key()) {
-                return false;
+    fn mapping(&self, node: usize) -> Option<&Mapping<A, T>> {
+        self.nodes[node]
+            .mapping()
+            .map(|mapping| &self.mappings[mapping as usize].mapping)
+    }
+
+    /// The node reached by pressing the key at the given node. Keys of a choice keep the cursor
+    /// where it is, so they can be repeated.
+    fn child(&self, node: usize, key: &KeyPress) -> Option<usize> {
+        match &self.nodes[node] {
+            Repeatable(mapping, _)
+                if self.mappings[*mapping as usize]
+                    .choice_keys
+                    .binary_search(key)
+                    .is_ok() =>
+            {
+                Some(node)
             }
+            node => node.child(key),
+        }
+    }
+}
+
+struct Builder<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    arena: Arena<A, T>,
+    interned: HashMap<Mapping<A, T>, u32>,
+    context: MapContext,
+}
+
+impl<A, T> Builder<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn intern(&mut self, mapping: &Mapping<A, T>) -> u32 {
+        if let Some(&id) = self.interned.get(mapping) {
+            return id;
         }
 
-        true
+        let mut choice_keys: Vec<KeyPress> = match mapping {
+            Single(_) => vec![],
+            Choice(behaviours, _) => behaviours.0.iter().map(|b| b.get_key()).collect(),
+        };
+        choice_keys.sort();
+        choice_keys.dedup();
+
+        let id = self.arena.mappings.len() as u32;
+        self.arena.mappings.push(Interned {
+            mapping: mapping.clone(),
+            choice_keys: choice_keys.into_boxed_slice(),
+        });
+        self.interned.insert(mapping.clone(), id);
+        id
+    }
+
+    fn child_or_insert(&mut self, node: usize, key: KeyPress, new: MappingTrieNode) -> usize {
+        let child = self.arena.nodes.len() as u32;
+        let edges = self.arena.nodes[node].edges_mut();
+
+        match edges.binary_search_by(|(k, _)| k.cmp(&key)) {
+            Ok(i) => edges[i].1 as usize,
+            Err(i) => {
+                edges.insert(i, (key, child));
+                self.arena.nodes.push(new);
+                child as usize
+            }
+        }
     }
 
-    fn map(root: &mut MappingTrieNode<A, T>, mapping: &Vec<Mapping<A, T>>, starting_pos: usize) {
+    fn map(
+        &mut self,
+        root: usize,
+        mapping: &[Mapping<A, T>],
+        starting_pos: usize,
+        path: &mut Vec<KeyPress>,
+    ) {
         let mut node = root;
 
         for i in starting_pos..mapping.len() {
             let m = &mapping[i];
 
             match m {
-                Single(behaviour) => match node {
-                    Root(next) | OneOff(_, next) => {
-                        node = next
-                            .entry(behaviour.get_key().clone())
-                            .or_insert(OneOff(m.clone(), HashMap::new()));
-                    }
-                    Repeatable(_, _, next) => {
-                        node = next
-                            .entry(behaviour.get_key().clone())
-                            .or_insert(OneOff(m.clone(), HashMap::new()));
+                Single(behaviour) => {
+                    let key = behaviour.get_key();
+                    path.push(key.clone());
+
+                    // Keys of a choice repeat it, so the step can't be reached after it.
+                    if let Repeatable(choice, _) = self.arena.nodes[node] {
+                        if self.arena.child(node, &key) == Some(node) {
+                            let reason = format!(
+                                "the key repeats the choice before it: {}",
+                                self.arena.mappings[choice as usize].mapping
+                            );
+                            self.context.conflict(path, reason);
+                            break;
+                        }
                     }
-                },
-                Choice(behaviours, _) => match node {
-                    Root(next) | OneOff(_, next) => {
-                        if Self::all_keys_available(next, behaviours) {
-                            let set: HashSet<KeyPress> =
-                                behaviours.0.iter().map(|b| b.get_key()).collect();
-
-                            behaviours.0.iter().for_each(|b| {
-                                let next_node = next
-                                    .entry(b.get_key().clone())
-                                    .or_insert(Repeatable(m.clone(), set.clone(), HashMap::new()));
-                                Self::map(next_node, mapping, i + 1);
-                            });
 
+                    let existing = self.arena.nodes[node]
+                        .child(&key)
+                        .and_then(|child| self.arena.mapping(child));
+
+                    match existing {
+                        Some(Choice(_, tag)) => {
+                            let reason = format!("the key is part of choice ({})", tag);
+                            self.context.conflict(path, reason);
+                            break;
+                        }
+                        // Whatever follows a key firing right away can't be reached.
+                        Some(existing @ Single(Behaviour::Action(..) | Behaviour::Shutdown(_)))
+                            if i + 1 < mapping.len() =>
+                        {
+                            let reason = format!("the key is already mapped to {}", existing);
+                            self.context.conflict(path, reason);
                             break;
-                        } else {
-                            println!("Not all keys are available for mapping: {:?}", mapping);
+                        }
+                        Some(existing)
+                            if existing != m && !matches!(behaviour, Behaviour::Timeout(_)) =>
+                        {
+                            let reason = format!("the key is already mapped to {}", existing);
+                            self.context.conflict(path, reason);
                             break;
                         }
+                        _ => {}
+                    }
+
+                    let id = self.intern(m);
+                    node = self.child_or_insert(node, key, OneOff(id, vec![]));
+                }
+                Choice(behaviours, tag) => {
+                    if let Repeatable(conflicting_mapping, _) = self.arena.nodes[node] {
+                        let reason = format!(
+                            "choice ({}) directly follows another choice: {}",
+                            tag, self.arena.mappings[conflicting_mapping as usize].mapping
+                        );
+                        self.context.conflict(path, reason);
+                        break;
                     }
-                    Repeatable(conflicting_mapping, _, _) => {
-                        println!(
-                                "Repeatable mapping conflicts with another repeatable mapping. Mapping: {}, conflicting mapping: {}",
-                                m, conflicting_mapping);
+
+                    let taken = behaviours
+                        .0
+                        .iter()
+                        .map(|b| b.get_key())
+                        .find(|key| self.arena.nodes[node].child(key).is_some());
+
+                    if let Some(taken) = taken {
+                        path.push(taken);
+                        let reason = format!("not all keys of the choice ({}) are available", tag);
+                        self.context.conflict(path, reason);
                         break;
                     }
-                },
+
+                    let id = self.intern(m);
+
+                    behaviours.0.iter().for_each(|b| {
+                        let next_node =
+                            self.child_or_insert(node, b.get_key(), Repeatable(id, vec![]));
+                        let mut path = path.clone();
+                        path.push(b.get_key());
+                        self.map(next_node, mapping, i + 1, &mut path);
+                    });
+
+                    break;
+                }
             }
         }
     }
 
-    pub fn from_mappings(mappings: &Vec<Vec<Mapping<A, T>>>) -> Self {
-        let mut root: MappingTrieNode<A, T> = Root(HashMap::new());
+    fn map_all(&mut self, sequences: &[Vec<Mapping<A, T>>]) {
+        for mapping in sequences {
+            self.map(0, mapping, 0, &mut vec![]);
+        }
+    }
+}
+
+/// Mappings compiled into a trie of key presses, see [`crate::fragment::Mappings`]. Nodes live in
+/// an arena, the root being the first one, so a [`Cursor`] is just an index. Each distinct mapping
+/// is stored once and nodes refer to it, and the arena is shared between clones.
+#[derive(Clone)]
+pub struct MappingTrie<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Send + Sync + Hash,
+{
+    arena: Arc<Arena<A, T>>,
+}
+
+impl<A, T> MappingTrie<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    /// Builds the trie, expanding mounted fragments. Sequences conflicting with the ones mapped
+    /// before them are left out and reported.
+    pub fn new(mappings: &Mappings<A, T>) -> (Self, Vec<Conflict>) {
+        let mut builder = Builder {
+            arena: Arena {
+                nodes: vec![Root(vec![])],
+                mappings: vec![],
+            },
+            interned: HashMap::new(),
+            context: MapContext {
+                mount: None,
+                conflicts: vec![],
+            },
+        };
+
+        builder.map_all(&mappings.sequences);
+
+        for (path, fragment) in &mappings.mounts {
+            let prefix = mappings.prefix(path);
+            builder.context.mount = Some(MountPoint {
+                fragment: fragment.name().to_string(),
+                path: path.clone(),
+            });
+
+            let sequences: Vec<_> = fragment
+                .sequences()
+                .iter()
+                .map(|sequence| [prefix.clone(), sequence.clone()].concat())
+                .collect();
+
+            builder.map_all(&sequences);
+        }
+
+        let Builder { arena, context, .. } = builder;
+        let trie = Self {
+            arena: Arc::new(arena),
+        };
+
+        (trie, context.conflicts)
+    }
+
+    pub fn root(&self) -> NodeView<'_, A, T> {
+        self.node(Cursor::root())
+    }
 
-        for mapping in mappings {
-            Self::map(&mut root, mapping, 0);
+    /// The node the cursor points at.
+    pub fn node(&self, cursor: Cursor) -> NodeView<'_, A, T> {
+        NodeView {
+            arena: &self.arena,
+            index: cursor.0,
         }
+    }
 
-        Self { root }
+    /// All nodes, the root first.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeView<'_, A, T>> {
+        (0..self.arena.nodes.len()).map(|index| self.node(Cursor(index)))
     }
 
+    /// Finds the mapping of the key pressed after the keys in the buffer.
     pub fn find_mapping(&self, key: &KeyPress, buffer: &[KeyPress]) -> Option<&Mapping<A, T>> {
-        let mut node = &self.root;
+        let node = buffer
+            .iter()
+            .try_fold(0, |node, key_press| self.arena.child(node, key_press))?;
 
-        for key_press in buffer {
-            let key = key_press;
+        self.arena.mapping(self.arena.child(node, key)?)
+    }
+}
 
-            match node {
-                Root(next) | OneOff(_, next) => {
-                    if !next.contains_key(key) {
-                        return None;
-                    } else {
-                        node = &next.get(key).unwrap();
-                    }
-                }
-                Repeatable(_, repeatable_set, next) => {
-                    if !repeatable_set.contains(key) {
-                        match next.get(key) {
-                            None => {
-                                return None;
-                            }
-                            Some(next_node) => {
-                                node = next_node;
-                            }
-                        }
-                    }
-                }
-            }
+/// What a node of the trie is part of.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum NodeKind {
+    Root,
+    /// A key of a sequence.
+    OneOff,
+    /// A key of a choice, which keeps the cursor on the node when any key of the choice is pressed.
+    Repeatable,
+}
+
+/// A read-only view of a node of a [`MappingTrie`].
+pub struct NodeView<'a, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    arena: &'a Arena<A, T>,
+    index: usize,
+}
+
+impl<A, T> Clone for NodeView<'_, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A, T> Copy for NodeView<'_, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+}
+
+impl<'a, A, T> NodeView<'a, A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    /// The node's index in the trie, the root's being 0.
+    pub fn id(&self) -> usize {
+        self.index
+    }
+
+    pub fn cursor(&self) -> Cursor {
+        Cursor(self.index)
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        match self.arena.nodes[self.index] {
+            Root(_) => NodeKind::Root,
+            OneOff(_, _) => NodeKind::OneOff,
+            Repeatable(_, _) => NodeKind::Repeatable,
         }
+    }
 
-        match node {
-            Root(next) | OneOff(_, next) => match next.get(key) {
-                None => None,
-                Some(keys) => match keys {
-                    Root(_) => None,
-                    OneOff(mapping, _) | Repeatable(mapping, _, _) => Some(mapping),
-                },
-            },
-            Repeatable(mapping, repeatable_set, next) => {
-                if repeatable_set.contains(key) {
-                    return Some(mapping);
-                }
+    /// The mapping the node was built from, a whole choice for repeatable nodes.
+    pub fn mapping(&self) -> Option<&'a Mapping<A, T>> {
+        self.arena.mapping(self.index)
+    }
+
+    /// The tag of the choice the node belongs to.
+    pub fn tag(&self) -> Option<&'a T> {
+        match self.mapping()? {
+            Single(_) => None,
+            Choice(_, tag) => Some(tag),
+        }
+    }
+
+    /// The behaviour of the key when pressed to reach the node.
+    pub fn behaviour(&self, key: &KeyPress) -> Option<&'a Behaviour<A>> {
+        match self.mapping()? {
+            Single(behaviour) => Some(behaviour).filter(|b| b.get_key() == *key),
+            Choice(behaviours, _) => behaviours.get_mapping(key),
+        }
+    }
+
+    /// Keys that keep the cursor on the node, sorted.
+    pub fn repeatable_keys(&self) -> &'a [KeyPress] {
+        match self.arena.nodes[self.index] {
+            Repeatable(mapping, _) => &self.arena.mappings[mapping as usize].choice_keys,
+            _ => &[],
+        }
+    }
+
+    /// The keys leading out of the node and the nodes they lead to, sorted by key.
+    pub fn edges(&self) -> impl Iterator<Item = (&'a KeyPress, NodeView<'a, A, T>)> + 'a {
+        let arena = self.arena;
 
-                match next.get(key) {
-                    None => None,
-                    Some(next_node) => match next_node {
-                        Root(_) => None,
-                        OneOff(mapping, _) | Repeatable(mapping, _, _) => Some(mapping),
+        arena.nodes[self.index]
+            .edges()
+            .iter()
+            .map(move |(key, index)| {
+                (
+                    key,
+                    NodeView {
+                        arena,
+                        index: *index as usize,
                     },
-                }
-            }
+                )
+            })
+    }
+}
+
+impl<A, T> MappingLookup<A, T> for MappingTrie<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn advance(
+        &self,
+        cursor: Cursor,
+        key: &KeyPress,
+    ) -> Option<(Cursor, &Behaviour<A>, Option<&T>)> {
+        let node = self.arena.child(cursor.0, key)?;
+
+        match self.arena.mapping(node)? {
+            Single(behaviour) => Some((Cursor(node), behaviour, None)),
+            Choice(behaviours, tag) => behaviours
+                .get_mapping(key)
+                .map(|b| (Cursor(node), b, Some(tag))),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::types::Behaviours;
+    use crate::types::Key;
+
+    #[test]
+    fn should_store_each_mapping_once() {
+        // Given
+        let mappings = keymap! {
+            "<A-a> [12]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two" on timeout },
+            "<A-a> s [12]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two" on timeout },
+        };
+
+        // When
+        let (trie, conflicts) = MappingTrie::new(&mappings.into());
+        let clone = trie.clone();
+
+        // Then
+        assert!(conflicts.is_empty());
+        assert_eq!(trie.arena.nodes.len(), 7);
+        assert_eq!(trie.arena.mappings.len(), 3);
+        assert!(Arc::ptr_eq(&trie.arena, &clone.arena));
+    }
+
+    #[test]
+    fn should_report_sequences_continuing_past_an_action() {
+        // Given
+        let mappings: Vec<Vec<Mapping<&str, &str>>> = vec![
+            vec![
+                Single(Behaviour::Timeout(Key::KeyA.into())),
+                Single(Behaviour::Action(Key::KeyB.into(), "B")),
+            ],
+            vec![
+                Single(Behaviour::Timeout(Key::KeyA.into())),
+                Single(Behaviour::Timeout(Key::KeyB.into())),
+                Single(Behaviour::Action(Key::KeyC.into(), "C")),
+            ],
+        ];
+
+        // When
+        let (trie, conflicts) = MappingTrie::new(&mappings.into());
+
+        // Then
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, [Key::KeyA.into(), Key::KeyB.into()]);
+        assert!(trie.advance(Cursor(2), &Key::KeyC.into()).is_none());
+    }
+
+    #[test]
+    fn should_report_steps_repeating_the_choice_before_them() {
+        // Given
+        let choice = Behaviours(vec![
+            Behaviour::ActionOnTimeout(Key::Key1.into(), "One"),
+            Behaviour::ActionOnTimeout(Key::Key2.into(), "Two"),
+        ]);
+        let mappings: Vec<Vec<Mapping<&str, &str>>> = vec![vec![
+            Single(Behaviour::Timeout(Key::KeyA.into())),
+            Choice(choice, "Channels"),
+            Single(Behaviour::Action(Key::Key2.into(), "Solo")),
+        ]];
+
+        // When
+        let (_, conflicts) = MappingTrie::new(&mappings.into());
+
+        // Then
+        let paths: Vec<_> = conflicts.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(
+            paths,
+            [
+                vec![Key::KeyA.into(), Key::Key1.into(), Key::Key2.into()],
+                vec![Key::KeyA.into(), Key::Key2.into(), Key::Key2.into()],
+            ]
+        );
+    }
+}
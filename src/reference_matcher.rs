@@ -0,0 +1,292 @@
+use crate::mapping_trie::Cursor;
+use crate::mapping_trie::MappingLookup;
+use crate::types::Behaviour;
+use crate::types::Mapping;
+use crate::types::Mapping::{Choice, Single};
+use crate::KeyPress;
+use core::hash::Hash;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::sync::Mutex;
+
+/// A naive matcher the trie is checked against: every key press scans the sequences in order,
+/// matching the keys pressed since the last reset like a pattern where a choice stands for one or
+/// more of its keys. The first sequence to match decides, as the first sequence mapped to a node
+/// of the trie owns it.
+pub(crate) struct ReferenceMatcher<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    sequences: Vec<Vec<Mapping<A, T>>>,
+    /// The keys pressed to reach each cursor handed out so far, the root being the first.
+    histories: Mutex<Vec<Vec<KeyPress>>>,
+}
+
+impl<A, T> ReferenceMatcher<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    pub fn new(sequences: Vec<Vec<Mapping<A, T>>>) -> Self {
+        Self {
+            sequences,
+            histories: Mutex::new(vec![vec![]]),
+        }
+    }
+}
+
+fn behaviour_of<'a, A>(behaviours: &'a [Behaviour<A>], key: &KeyPress) -> Option<&'a Behaviour<A>>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    behaviours
+        .iter()
+        .find(|behaviour| behaviour.get_key() == *key)
+}
+
+/// Matches all the keys against the start of the sequence, a choice taking keys for as long as
+/// they're part of it. Returns the behaviour of the last key, along with the tag of its choice.
+fn match_sequence<'a, A, T>(
+    sequence: &'a [Mapping<A, T>],
+    keys: &[KeyPress],
+) -> Option<(&'a Behaviour<A>, Option<&'a T>)>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    let mut step: usize = 0;
+    let mut last = None;
+
+    for key in keys {
+        let repeated = match step.checked_sub(1).map(|previous| &sequence[previous]) {
+            Some(Choice(behaviours, tag)) => {
+                behaviour_of(&behaviours.0, key).map(|behaviour| (behaviour, Some(tag)))
+            }
+            _ => None,
+        };
+
+        last = match (repeated, sequence.get(step)) {
+            (Some(found), _) => Some(found),
+            (None, Some(Single(behaviour))) if behaviour.get_key() == *key => {
+                step += 1;
+                Some((behaviour, None))
+            }
+            (None, Some(Choice(behaviours, tag))) => {
+                step += 1;
+                Some((behaviour_of(&behaviours.0, key)?, Some(tag)))
+            }
+            _ => return None,
+        };
+    }
+
+    last
+}
+
+impl<A, T> MappingLookup<A, T> for ReferenceMatcher<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn advance(
+        &self,
+        cursor: Cursor,
+        key: &KeyPress,
+    ) -> Option<(Cursor, &Behaviour<A>, Option<&T>)> {
+        let mut histories = self.histories.lock().unwrap();
+        let mut keys = histories[cursor.0].clone();
+        keys.push(key.clone());
+
+        let (behaviour, tag) = self
+            .sequences
+            .iter()
+            .find_map(|sequence| match_sequence(sequence, &keys))?;
+
+        histories.push(keys);
+        Some((Cursor(histories.len() - 1), behaviour, tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::engine::DEFAULT_TIMEOUT;
+    use crate::mapping_trie::MappingTrie;
+    use crate::types::Behaviours;
+    use crate::types::Key::*;
+    use crate::types::Modifier::*;
+    use proptest::prelude::*;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn letters() -> Vec<KeyPress> {
+        vec![KeyPress::Mod(KeyA, ModAlt), KeyA.into(), KeyB.into()]
+    }
+
+    fn digits() -> Vec<KeyPress> {
+        vec![Key1.into(), Key2.into(), Key3.into()]
+    }
+
+    fn key_press() -> impl Strategy<Value = KeyPress> {
+        proptest::sample::select([letters(), digits()].concat())
+    }
+
+    fn behaviour(key: KeyPress) -> impl Strategy<Value = Behaviour<u8>> {
+        prop_oneof![
+            4 => Just(Behaviour::Timeout(key.clone())),
+            3 => (0..8u8).prop_map({
+                let key = key.clone();
+                move |action| Behaviour::Action(key.clone(), action)
+            }),
+            3 => (0..8u8).prop_map({
+                let key = key.clone();
+                move |action| Behaviour::ActionOnTimeout(key.clone(), action)
+            }),
+            1 => Just(Behaviour::Shutdown(key)),
+        ]
+    }
+
+    /// What a key leading further may do: wait, or fire on timeout unless another key follows.
+    fn prefix_behaviour(key: KeyPress) -> impl Strategy<Value = Behaviour<u8>> {
+        prop_oneof![
+            2 => Just(Behaviour::Timeout(key.clone())),
+            1 => (0..8u8).prop_map(move |action| Behaviour::ActionOnTimeout(key.clone(), action)),
+        ]
+    }
+
+    /// A choice on some of the keys, possibly followed by a letter, which isn't part of it.
+    fn choice(keys: Vec<KeyPress>) -> BoxedStrategy<Option<Vec<Mapping<u8, u8>>>> {
+        if keys.is_empty() {
+            return Just(None).boxed();
+        }
+
+        let size = keys.len();
+        let choice = (proptest::sample::subsequence(keys, 1..=size), 0..3u8)
+            .prop_flat_map(|(keys, tag)| {
+                let behaviours: Vec<_> = keys.into_iter().map(behaviour).collect();
+
+                (behaviours, Just(tag))
+            })
+            .prop_map(|(behaviours, tag)| Choice(Behaviours(behaviours), tag));
+        let next = proptest::option::of(
+            proptest::sample::select(letters())
+                .prop_flat_map(behaviour)
+                .prop_map(Single),
+        );
+
+        proptest::option::weighted(0.3, (choice, next))
+            .prop_map(|sequence| {
+                sequence.map(|(choice, next)| std::iter::once(choice).chain(next).collect())
+            })
+            .boxed()
+    }
+
+    /// The sequences of a tree of keys, each key leading to a behaviour of its own, so they can't
+    /// conflict. The trie isn't asked which keymaps are valid, as it's the one being checked.
+    fn sequences(depth: u32) -> BoxedStrategy<Vec<Vec<Mapping<u8, u8>>>> {
+        proptest::sample::subsequence([letters(), digits()].concat(), 1..=3)
+            .prop_flat_map(move |keys| {
+                let free: Vec<_> = digits().into_iter().filter(|k| !keys.contains(k)).collect();
+                let branches: Vec<_> = keys.into_iter().map(|key| branch(key, depth)).collect();
+
+                (branches, choice(free))
+            })
+            .prop_map(|(branches, choice)| branches.into_iter().flatten().chain(choice).collect())
+            .boxed()
+    }
+
+    /// The sequences starting with the key: the key alone, or followed by sequences of its own.
+    fn branch(key: KeyPress, depth: u32) -> BoxedStrategy<Vec<Vec<Mapping<u8, u8>>>> {
+        let alone = behaviour(key.clone()).prop_map(|behaviour| vec![vec![Single(behaviour)]]);
+
+        if depth == 0 {
+            return alone.boxed();
+        }
+
+        let further = (prefix_behaviour(key), sequences(depth - 1)).prop_map(|(first, rest)| {
+            rest.into_iter()
+                .map(|sequence| [vec![Single(first.clone())], sequence].concat())
+                .collect()
+        });
+
+        prop_oneof![2 => alone, 1 => further].boxed()
+    }
+
+    fn keymap() -> impl Strategy<Value = Vec<Vec<Mapping<u8, u8>>>> {
+        sequences(3)
+    }
+
+    /// Key presses along with how long to wait before each of them.
+    fn key_stream() -> impl Strategy<Value = Vec<(u64, KeyPress)>> {
+        let wait = prop_oneof![3 => Just(0), 1 => 0..2 * DEFAULT_TIMEOUT.as_millis() as u64];
+
+        proptest::collection::vec((wait, key_press()), 0..24)
+    }
+
+    /// What the engine did for each key press: the decision, the events sent while waiting and
+    /// right away, and how long until the deadline.
+    fn run(
+        mut engine: Engine<u8, u8>,
+        keys: &[(u64, KeyPress)],
+    ) -> Vec<(String, Option<Duration>)> {
+        let mut now = Instant::now();
+        let mut trace = vec![];
+
+        for (wait, key) in keys {
+            now += Duration::from_millis(*wait);
+            let timed_out = engine.tick(now);
+            let (hook_action, events) = engine.feed(key, now);
+
+            trace.push((
+                format!("{}: {:?} {:?} {:?}", key, timed_out, hook_action, events),
+                engine.next_deadline().map(|deadline| deadline - now),
+            ));
+        }
+
+        let flushed = engine.stop(crate::types::FlushPolicy::Flush);
+        trace.push((format!("stop: {:?}", flushed), None));
+        trace
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_like_the_reference(keymap in keymap(), keys in key_stream()) {
+            let (trie, conflicts) = MappingTrie::new(&keymap.clone().into());
+            prop_assert!(conflicts.is_empty(), "{:?}", conflicts);
+
+            let expected = run(Engine::from_lookup(Box::new(ReferenceMatcher::new(keymap))), &keys);
+            let actual = run(Engine::from_trie(trie), &keys);
+
+            prop_assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn should_keep_matching_a_choice_before_the_next_key() {
+        // Given
+        let sequence = vec![
+            Single(Behaviour::Timeout(KeyPress::Mod(KeyA, ModAlt))),
+            Choice(
+                Behaviours(vec![
+                    Behaviour::ActionOnTimeout(Key1.into(), 1),
+                    Behaviour::ActionOnTimeout(Key2.into(), 2),
+                ]),
+                0,
+            ),
+            Single(Behaviour::Action(KeyB.into(), 3)),
+        ];
+        let keys = [KeyPress::Mod(KeyA, ModAlt), Key1.into(), Key2.into()];
+
+        // When
+        let choice = match_sequence(&sequence, &keys);
+        let next = match_sequence(&sequence, &[&keys[..], &[KeyB.into()]].concat());
+
+        // Then
+        assert_eq!(
+            choice,
+            Some((&Behaviour::ActionOnTimeout(Key2.into(), 2), Some(&0)))
+        );
+        assert_eq!(next, Some((&Behaviour::Action(KeyB.into(), 3), None)));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::action_handler::ActionHandler;
+use crate::builtin;
+use crate::builtin::Builtin;
+use crate::config;
+use crate::config::ConfigMappings;
+use crate::error::ConfigError;
+use crate::types::Behaviour;
+use crate::types::Event;
+use crate::types::Mapping;
+use crate::types::SystemAction;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::RwLock;
+
+/// What an action's closure is called with.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ActionContext<'a> {
+    /// The action as written in the keymap, e.g. `ToggleChannel(3)`.
+    pub action: &'a str,
+    /// What's between the parentheses, e.g. `3`.
+    pub argument: Option<&'a str>,
+    /// The tag of the choice, for actions fired together on timeout.
+    pub tag: Option<&'a str>,
+    /// The actions of the choice, when the action is the choice's tag.
+    pub actions: &'a [String],
+    /// The keys picking those actions, as written in the keymap.
+    pub keys: &'a [String],
+}
+
+type Callback = Box<dyn Fn(&ActionContext) + Send + Sync>;
+type SystemCallback = Box<dyn Fn(&SystemAction) + Send + Sync>;
+
+/// Splits `ToggleChannel(3)` into the name it's registered under and its argument.
+fn split(action: &str) -> (&str, Option<&str>) {
+    action
+        .strip_suffix(')')
+        .and_then(|action| action.split_once('('))
+        .map_or((action, None), |(name, argument)| {
+            (name.trim(), Some(argument.trim()))
+        })
+}
+
+/// Actions named by strings and run by closures, for keymaps that don't need an enum of actions.
+/// Keymap files loaded through the registry fail on actions it doesn't know.
+///
+/// ```ignore
+/// let registry = ActionRegistry::new()
+///     .on("VolumeUp", |_| mixer.volume_up())
+///     .on("ToggleChannel", |ctx| mixer.toggle(ctx.argument.unwrap().parse().unwrap()));
+/// let mappings = registry.load("mixer.keymap")?;
+///
+/// KeyboardHook::new(mappings, Box::new(registry)).hook()?;
+/// ```
+#[derive(Default)]
+pub struct ActionRegistry {
+    actions: HashMap<String, Callback>,
+    system: Option<SystemCallback>,
+    builtins: bool,
+    loaded: RwLock<Loaded>,
+}
+
+/// What the registry learns from the keymaps it loads.
+#[derive(Default)]
+struct Loaded {
+    /// Built-ins by action, parsed once when loading.
+    builtins: HashMap<String, Builtin>,
+    /// The key picking each action of a choice, by the choice's tag.
+    keys: HashMap<String, HashMap<String, String>>,
+}
+
+impl ActionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the closure for the action, and for every `name(argument)` of it.
+    pub fn on(mut self, name: &str, f: impl Fn(&ActionContext) + Send + Sync + 'static) -> Self {
+        self.actions.insert(name.to_string(), Box::new(f));
+        self
+    }
+
+    pub fn on_system(mut self, f: impl Fn(&SystemAction) + Send + Sync + 'static) -> Self {
+        self.system = Some(Box::new(f));
+        self
+    }
+
+    /// Knows the built-in actions, see [`Builtin`]. Closures registered under the same names win.
+    pub fn with_builtins(mut self) -> Self {
+        self.builtins = true;
+        self
+    }
+
+    pub fn contains(&self, action: &str) -> bool {
+        self.known(action) == Ok(true)
+    }
+
+    /// Whether the action is registered, failing on a built-in with malformed arguments.
+    fn known(&self, action: &str) -> Result<bool, String> {
+        match self.builtin(action) {
+            Some(builtin) => builtin.map(|_| true),
+            None => Ok(self.actions.contains_key(split(action).0)),
+        }
+    }
+
+    /// The action as a built-in, parsed on first sight, unless a closure is registered for it.
+    fn builtin(&self, action: &str) -> Option<Result<Builtin, String>> {
+        let (name, argument) = split(action);
+
+        if !self.builtins || self.actions.contains_key(name) || !builtin::NAMES.contains(&name) {
+            return None;
+        }
+
+        let loaded = self
+            .loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(builtin) = loaded.builtins.get(action) {
+            return Some(Ok(builtin.clone()));
+        }
+
+        drop(loaded);
+        let builtin = Builtin::parse(name, argument.unwrap_or_default());
+
+        if let Ok(builtin) = &builtin {
+            self.loaded
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .builtins
+                .insert(action.to_string(), builtin.clone());
+        }
+
+        Some(builtin)
+    }
+
+    pub fn parse(&self, source: &str) -> Result<ConfigMappings, ConfigError> {
+        let mappings = config::parse_with(source, &|action| self.known(action))?;
+        let mut loaded = self
+            .loaded
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for mapping in mappings.iter().flatten() {
+            let Mapping::Choice(behaviours, tag) = mapping else {
+                continue;
+            };
+            let keys = loaded.keys.entry(tag.clone()).or_default();
+
+            for behaviour in &behaviours.0 {
+                if let Behaviour::Action(key, action) | Behaviour::ActionOnTimeout(key, action) =
+                    behaviour
+                {
+                    keys.insert(action.clone(), key.to_string().to_lowercase());
+                }
+            }
+        }
+
+        Ok(mappings)
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<ConfigMappings, ConfigError> {
+        self.parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Runs the closures of the event's actions. A choice whose tag is a registered action runs
+    /// that action once, with the choice's actions in the context.
+    pub fn run(&self, event: &Event<String, String>) {
+        match event {
+            Event::Single(action) => self.call(action, None, &[]),
+            Event::Multi(tag, actions) if self.contains(tag) => self.call(tag, None, actions),
+            Event::Multi(tag, actions) => {
+                for action in actions {
+                    self.call(action, Some(tag), &[]);
+                }
+            }
+            Event::System(action) => {
+                if let Some(system) = &self.system {
+                    system(action);
+                }
+            }
+        }
+    }
+
+    fn call(&self, action: &str, tag: Option<&str>, actions: &[String]) {
+        let (name, argument) = split(action);
+        let keys = self.keys(action, actions);
+
+        if let Some(builtin) = self.builtin(action) {
+            match builtin.map(|builtin| builtin.interpolate(actions, &keys).execute()) {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => tracing::warn!(action, %error, "Action failed"),
+                Err(error) => tracing::warn!(action, %error, "Malformed action"),
+            }
+
+            return;
+        }
+
+        match self.actions.get(name) {
+            Some(f) => f(&ActionContext {
+                action,
+                argument,
+                tag,
+                actions,
+                keys: &keys,
+            }),
+            None => tracing::warn!(action, "No action registered"),
+        }
+    }
+
+    /// The keys picking the actions of a choice tagged with the action, for the keymaps loaded.
+    fn keys(&self, tag: &str, actions: &[String]) -> Vec<String> {
+        let loaded = self
+            .loaded
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(keys) = loaded.keys.get(tag) else {
+            return vec![];
+        };
+
+        actions
+            .iter()
+            .filter_map(|action| keys.get(action).cloned())
+            .collect()
+    }
+}
+
+impl ActionHandler<String, String> for ActionRegistry {
+    fn handle(&self, receiver: mpsc::Receiver<Event<String, String>>) {
+        for event in receiver {
+            self.run(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Mapping;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn should_call_the_closures_of_the_actions() {
+        // Given
+        let calls = Arc::new(Mutex::new(vec![]));
+        let registry = ActionRegistry::new().on("ToggleChannel", {
+            let calls = calls.clone();
+            move |ctx| {
+                let call = format!("{:?} {:?}", ctx.argument, ctx.tag);
+                calls.lock().unwrap().push(call);
+            }
+        });
+
+        // When
+        registry.run(&Event::Multi(
+            "Channels".to_string(),
+            vec![
+                "ToggleChannel(1)".to_string(),
+                "ToggleChannel(3)".to_string(),
+            ],
+        ));
+        registry.run(&Event::Single("VolumeUp".to_string()));
+
+        // Then
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                r#"Some("1") Some("Channels")"#,
+                r#"Some("3") Some("Channels")"#
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_unknown_actions_when_loading() {
+        // Given
+        let registry = ActionRegistry::new()
+            .on("Kenny", |_| {})
+            .on("ToggleChannel", |_| {});
+        let source = r#"
+            "<A-a> w" => Kenny
+            "<A-a> [1-3]*" as Channels => ToggleChannel(n) on timeout
+            "<A-a> [jk]*" as Volume => { j => VolumeDown, k => VolumeUp }
+        "#;
+
+        // When
+        let result = registry.parse(source);
+
+        // Then
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 4: no action is registered as `VolumeDown`"
+        );
+    }
+
+    #[test]
+    fn should_run_a_choice_tagged_with_a_builtin_once() {
+        // Given
+        let path = std::env::temp_dir().join(format!("keyboard_hook-{}.log", std::process::id()));
+        let registry = ActionRegistry::new().with_builtins();
+        let source = format!(
+            r#""<A-a> [1-5]*" as "write({}, toggle {{actions}} with {{keys}})" => ch(n) on timeout"#,
+            path.display()
+        );
+        let mappings = registry.parse(&source).unwrap();
+        let Mapping::Choice(_, tag) = &mappings[0][1] else {
+            panic!("Expected a choice");
+        };
+
+        // When
+        registry.run(&Event::Multi(
+            tag.clone(),
+            vec!["ch(1)".to_string(), "ch(3)".to_string()],
+        ));
+
+        // Then
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, "toggle ch(1) ch(3) with 1 3\n");
+    }
+
+    #[test]
+    fn should_report_malformed_builtins_when_loading() {
+        // Given
+        let registry = ActionRegistry::new().with_builtins();
+        let source = r#"
+            "<A-a> w" => print(Kenny)
+            "<A-a> q" => write(/tmp/keys)
+        "#;
+
+        // When
+        let result = registry.parse(source);
+
+        // Then
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 3: `write(/tmp/keys)`: expected `write(path, line)`"
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use crate::engine::Engine;
+use crate::error::KeyboardHookError;
+use crate::journal::Journal;
+use crate::types::Event;
+use crate::types::FlushPolicy;
+use crate::types::HookAction;
+use crate::types::SystemAction;
+use crate::KeyPress;
+use core::hash::Hash;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long handling a key press may take. Windows silently removes a low-level hook whose
+/// callback keeps exceeding `LowLevelHooksTimeout`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Budget {
+    /// Handling a key press taking longer sends a `SystemAction::OverBudget` event.
+    pub callback: Duration,
+    /// How long to wait for the engine while the timer thread holds it, before passing the key
+    /// on unhandled.
+    pub lock: Duration,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            callback: Duration::from_millis(10),
+            lock: Duration::from_millis(5),
+        }
+    }
+}
+
+/// How long handling key presses took so far.
+#[derive(Default, Debug)]
+pub struct Latency {
+    key_presses: AtomicU64,
+    over_budget: AtomicU64,
+    worst_nanos: AtomicU64,
+}
+
+impl Latency {
+    pub fn key_presses(&self) -> u64 {
+        self.key_presses.load(Ordering::Relaxed)
+    }
+
+    /// How many key presses took longer than the budget.
+    pub fn over_budget(&self) -> u64 {
+        self.over_budget.load(Ordering::Relaxed)
+    }
+
+    pub fn worst(&self) -> Duration {
+        Duration::from_nanos(self.worst_nanos.load(Ordering::Relaxed))
+    }
+
+    fn record(&self, elapsed: Duration, budget: Duration) -> bool {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.key_presses.fetch_add(1, Ordering::Relaxed);
+        self.worst_nanos.fetch_max(nanos, Ordering::Relaxed);
+
+        let over_budget = elapsed > budget;
+
+        if over_budget {
+            self.over_budget.fetch_add(1, Ordering::Relaxed);
+        }
+
+        over_budget
+    }
+}
+
+/// Where events go: the channel, and the journal if there is one.
+#[derive(Clone)]
+struct Outbox<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    sender: mpsc::Sender<Event<A, T>>,
+    journal: Option<Arc<Journal>>,
+}
+
+impl<A, T> Outbox<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn send(&self, events: impl IntoIterator<Item = Event<A, T>>) -> Result<(), KeyboardHookError> {
+        for event in events {
+            if let Some(journal) = &self.journal {
+                journal.event(&event);
+            }
+
+            self.sender.send(event)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct SharedState<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    engine: Engine<A, T>,
+    outbox: Outbox<A, T>,
+    closed: bool,
+}
+
+type State<A, T> = Arc<(Mutex<SharedState<A, T>>, Condvar)>;
+
+/// Drives an [`Engine`] from a backend's key callback, sending its events to a channel. A single
+/// timer thread, started along with the runtime, ticks the engine when a deadline passes, so
+/// handling a key press neither spawns threads nor allocates.
+pub struct Runtime<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    state: State<A, T>,
+    timer: Option<JoinHandle<()>>,
+    /// For events sent without the engine, which may be held by the timer thread.
+    outbox: Outbox<A, T>,
+    budget: Budget,
+    latency: Arc<Latency>,
+    /// Mirrors the engine's, so the hook thread can tell without waiting for the engine.
+    stopped: AtomicBool,
+}
+
+impl<A, T> Runtime<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    pub fn new(engine: Engine<A, T>, sender: mpsc::Sender<Event<A, T>>) -> Self {
+        let outbox = Outbox {
+            sender,
+            journal: None,
+        };
+        let state: State<A, T> = Arc::new((
+            Mutex::new(SharedState {
+                engine,
+                outbox: outbox.clone(),
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let timer_state = Arc::clone(&state);
+        let timer = thread::spawn(move || {
+            if let Err(error) = Self::run_timer(&timer_state) {
+                tracing::warn!(%error, "Timer stopped");
+            }
+        });
+
+        Self {
+            state,
+            timer: Some(timer),
+            outbox,
+            budget: Budget::default(),
+            latency: Arc::new(Latency::default()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Records the events sent to the journal, see [`Journal`].
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        {
+            let mut state = self.lock();
+            journal.timeout(state.engine.timeout());
+            state.outbox.journal = Some(Arc::clone(&journal));
+        }
+
+        self.outbox.journal = Some(journal);
+        self
+    }
+
+    pub fn latency(&self) -> Arc<Latency> {
+        Arc::clone(&self.latency)
+    }
+
+    /// Sleeps until the engine's next deadline, which moves with every key press of a sequence,
+    /// and ticks the engine once it passes.
+    fn run_timer(state: &(Mutex<SharedState<A, T>>, Condvar)) -> Result<(), KeyboardHookError> {
+        let (mutex, condvar) = state;
+        let mut state = mutex.lock()?;
+
+        loop {
+            if state.closed || state.engine.is_stopped() {
+                return Ok(());
+            }
+
+            state = match state.engine.next_deadline() {
+                None => condvar.wait(state)?,
+                Some(deadline) => {
+                    let now = Instant::now();
+
+                    if deadline <= now {
+                        let events = state.engine.tick(now);
+                        state.outbox.send(events)?;
+                        state
+                    } else {
+                        condvar.wait_timeout(state, deadline - now)?.0
+                    }
+                }
+            };
+        }
+    }
+
+    /// Feeds the key press to the engine and sends the event it triggered, if any. Fails with
+    /// `LockTimeout` if the engine can't be had within the budget, in which case the key should be
+    /// passed on.
+    pub fn handle(&self, key_press: &KeyPress) -> Result<HookAction, KeyboardHookError> {
+        let start = Instant::now();
+        let result = self.feed(key_press, start);
+        let elapsed = start.elapsed();
+
+        tracing::trace!(?elapsed, "Key press handled");
+
+        if self.latency.record(elapsed, self.budget.callback) {
+            tracing::warn!(?elapsed, budget = ?self.budget.callback, "Key press over budget");
+            self.outbox
+                .send([Event::System(SystemAction::OverBudget(elapsed))])?;
+        }
+
+        result
+    }
+
+    fn feed(&self, key_press: &KeyPress, start: Instant) -> Result<HookAction, KeyboardHookError> {
+        let (mutex, condvar) = &*self.state;
+        let mut state = loop {
+            match mutex.try_lock() {
+                Ok(state) => break state,
+                Err(TryLockError::Poisoned(_)) => return Err(KeyboardHookError::PoisonedState),
+                Err(TryLockError::WouldBlock) if start.elapsed() < self.budget.lock => {
+                    thread::yield_now()
+                }
+                Err(TryLockError::WouldBlock) => return Err(KeyboardHookError::LockTimeout),
+            }
+        };
+
+        let (hook_action, events) = state.engine.feed(key_press, Instant::now());
+        self.stopped
+            .store(state.engine.is_stopped(), Ordering::Release);
+        state.outbox.send(events)?;
+
+        drop(state);
+        condvar.notify_one();
+
+        Ok(hook_action)
+    }
+
+    /// Stops the engine, see [`Engine::stop`].
+    pub fn stop(&self, policy: FlushPolicy) -> Result<(), KeyboardHookError> {
+        let (mutex, condvar) = &*self.state;
+        let mut state = mutex.lock()?;
+        let events = state.engine.stop(policy);
+        self.stopped.store(true, Ordering::Release);
+        condvar.notify_one();
+
+        state.outbox.send(events)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SharedState<A, T>> {
+        let (mutex, _) = &*self.state;
+        mutex
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<A, T> Drop for Runtime<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn drop(&mut self) {
+        self.lock().closed = true;
+        self.state.1.notify_one();
+
+        if let Some(timer) = self.timer.take() {
+            let _ = timer.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use crate::types::Key::*;
+    use crate::*;
+    use std::time::Duration;
+
+    #[test]
+    fn should_send_actions_on_timeout_from_the_timer_thread() {
+        // Given
+        let (engine, _) = Engine::new(keymap! { "<A-a> q" => "Princess" on timeout });
+        let (tx, rx) = mpsc::channel::<Event<&str, &str>>();
+        let runtime = Runtime::new(engine.with_timeout(Duration::from_millis(10)), tx);
+
+        // When
+        runtime.handle(&alt!(KeyA)).unwrap();
+        runtime.handle(&key!(KeyQ)).unwrap();
+
+        // Then
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(1)),
+            Ok(Event::Single("Princess"))
+        );
+    }
+
+    #[test]
+    fn should_pass_keys_on_when_the_engine_is_held_too_long() {
+        // Given
+        let (engine, _) = Engine::new(keymap! { "w" => "Kenny" });
+        let (tx, rx) = mpsc::channel::<Event<&str, &str>>();
+        let runtime = Runtime::new(engine, tx).with_budget(Budget {
+            callback: Duration::from_millis(1),
+            lock: Duration::from_millis(2),
+        });
+        let state = runtime.lock();
+
+        // When
+        let result = runtime.handle(&key!(KeyW));
+        drop(state);
+
+        // Then
+        assert!(matches!(result, Err(KeyboardHookError::LockTimeout)));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::System(SystemAction::OverBudget(_)))
+        ));
+        assert_eq!(runtime.latency().over_budget(), 1);
+    }
+
+    #[test]
+    fn should_tell_it_stopped_while_the_engine_is_held() {
+        // Given
+        let (engine, _) = Engine::new(keymap! { "<A-a> q" => shutdown });
+        let (tx, _rx) = mpsc::channel::<Event<&str, &str>>();
+        let runtime = Runtime::new(engine, tx);
+        runtime.handle(&alt!(KeyA)).unwrap();
+        runtime.handle(&key!(KeyQ)).unwrap();
+
+        // When
+        let state = runtime.lock();
+        let stopped = runtime.is_stopped();
+        drop(state);
+
+        // Then
+        assert!(stopped);
+    }
+
+    #[test]
+    fn should_journal_over_budget_events() {
+        // Given
+        let path =
+            std::env::temp_dir().join(format!("keyboard_hook-{}.journal", std::process::id()));
+        let journal = Arc::new(Journal::create(&path).unwrap());
+        let (engine, _) = Engine::new(keymap! { "w" => "Kenny" });
+        let (tx, _rx) = mpsc::channel::<Event<&str, &str>>();
+        let runtime = Runtime::new(engine, tx)
+            .with_budget(Budget {
+                callback: Duration::ZERO,
+                lock: Duration::from_millis(5),
+            })
+            .with_journal(journal);
+
+        // When
+        runtime.handle(&key!(KeyW)).unwrap();
+
+        // Then
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let events: Vec<_> = written
+            .lines()
+            .filter(|line| line.starts_with("E "))
+            .map(|line| line.splitn(3, ' ').nth(2).unwrap())
+            .collect();
+        assert_eq!(events[0], "single Kenny");
+        assert!(events[1].starts_with("system OverBudget("));
+    }
+}
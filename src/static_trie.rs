@@ -0,0 +1,142 @@
+use crate::mapping_trie::Cursor;
+use crate::mapping_trie::MappingLookup;
+use crate::types::Behaviour;
+use crate::KeyPress;
+use core::hash::Hash;
+use std::fmt::Debug;
+use std::fmt::Display;
+
+/// A node of a [`StaticMappingTrie`]: the behaviour of the key leading to it, the tag of the
+/// choice it belongs to, and its children sorted by key press.
+pub struct StaticNode<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static,
+{
+    behaviour: Option<Behaviour<A>>,
+    tag: Option<T>,
+    edges: &'static [(KeyPress, usize)],
+}
+
+impl<A, T> StaticNode<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static,
+{
+    pub const fn new(
+        behaviour: Option<Behaviour<A>>,
+        tag: Option<T>,
+        edges: &'static [(KeyPress, usize)],
+    ) -> Self {
+        Self {
+            behaviour,
+            tag,
+            edges,
+        }
+    }
+}
+
+/// A mapping trie laid out at compile time by `static_keymap!`. Nodes live in a static slice, the
+/// root being the first one, so looking keys up is a binary search per key press with no hashing
+/// or allocations.
+pub struct StaticMappingTrie<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static,
+{
+    nodes: &'static [StaticNode<A, T>],
+}
+
+impl<A, T> StaticMappingTrie<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: 'static,
+{
+    pub const fn new(nodes: &'static [StaticNode<A, T>]) -> Self {
+        Self { nodes }
+    }
+
+    fn child(&self, node: usize, key: &KeyPress) -> Option<usize> {
+        let edges = self.nodes[node].edges;
+
+        edges
+            .binary_search_by(|(k, _)| k.cmp(key))
+            .ok()
+            .map(|i| edges[i].1)
+    }
+}
+
+impl<A, T> MappingLookup<A, T> for StaticMappingTrie<A, T>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    fn advance(
+        &self,
+        cursor: Cursor,
+        key: &KeyPress,
+    ) -> Option<(Cursor, &Behaviour<A>, Option<&T>)> {
+        let node = self.child(cursor.0, key)?;
+        let next = &self.nodes[node];
+
+        next.behaviour
+            .as_ref()
+            .map(|b| (Cursor(node), b, next.tag.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping_trie::MappingTrie;
+    use crate::types::Key::*;
+    use crate::*;
+
+    static MAPPINGS: StaticMappingTrie<&str, &str> = static_keymap! {
+        "<A-a> e x i t" => shutdown,
+        "<A-a> w" => "Kenny",
+        "<A-a> s 2" => "UseStrip2" on timeout,
+        "<A-a> s 2 [1-3]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two", 3 => timeout },
+        "<A-a> [jk]*" as "Volume" => { j => "VolumeDown", k => "VolumeUp" },
+    };
+
+    #[test]
+    fn should_find_the_same_behaviours_as_the_mapping_trie() {
+        let (trie, _) = MappingTrie::new(
+            &keymap! {
+                "<A-a> e x i t" => shutdown,
+                "<A-a> w" => "Kenny",
+                "<A-a> s 2" => "UseStrip2" on timeout,
+                "<A-a> s 2 [1-3]*" as "Channels" => { 1 => "One" on timeout, 2 => "Two", 3 => timeout },
+                "<A-a> [jk]*" as "Volume" => { j => "VolumeDown", k => "VolumeUp" },
+            }
+            .into(),
+        );
+
+        let sequences = [
+            vec![alt!(KeyA), key!(KeyE), key!(KeyX), key!(KeyI), key!(KeyT)],
+            vec![alt!(KeyA), key!(KeyW), key!(KeyW)],
+            vec![
+                alt!(KeyA),
+                key!(KeyS),
+                key!(Key2),
+                key!(Key1),
+                key!(Key3),
+                key!(Key2),
+            ],
+            vec![alt!(KeyA), key!(KeyJ), key!(KeyK), key!(KeyJ), key!(KeyX)],
+            vec![key!(KeyA), alt!(KeyA)],
+        ];
+
+        for sequence in sequences {
+            for i in 0..sequence.len() {
+                assert_eq!(
+                    MAPPINGS.find_behaviour(&sequence[i], &sequence[..i]),
+                    trie.find_behaviour(&sequence[i], &sequence[..i]),
+                    "{:?}",
+                    &sequence[..=i]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::action_handler::ActionHandler;
+use crate::types::Event;
+use futures_channel::mpsc as futures_mpsc;
+use futures_core::Stream;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::Context;
+use std::task::Poll;
+
+/// Creates a handler forwarding events into the returned stream. The handler runs on the
+/// dedicated handler thread, so polling the stream never blocks an async runtime.
+pub fn channel<A, T>() -> (StreamHandler<A, T>, EventStream<A, T>)
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    let (sender, receiver) = futures_mpsc::unbounded();
+
+    (StreamHandler { sender }, EventStream { receiver })
+}
+
+pub struct StreamHandler<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    sender: futures_mpsc::UnboundedSender<Event<A, T>>,
+}
+
+impl<A, T> ActionHandler<A, T> for StreamHandler<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn handle(&self, receiver: mpsc::Receiver<Event<A, T>>) {
+        for event in receiver {
+            // Keep draining the receiver if the stream was dropped, the hook still sends events.
+            let _ = self.sender.unbounded_send(event);
+        }
+
+        self.sender.close_channel();
+    }
+}
+
+/// Events of a [`crate::KeyboardHook`]. The stream ends when the keyboard is unhooked.
+pub struct EventStream<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    receiver: futures_mpsc::UnboundedReceiver<Event<A, T>>,
+}
+
+impl<A, T> Stream for EventStream<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    type Item = Event<A, T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SystemAction::*;
+    use futures_executor::block_on_stream;
+    use std::thread;
+
+    #[test]
+    fn should_forward_events_until_the_hook_stops_sending() {
+        // Given
+        let (handler, stream) = channel::<&str, &str>();
+        let (tx, rx) = mpsc::channel();
+        let consumer = thread::spawn(move || handler.handle(rx));
+
+        // When
+        tx.send(Event::System(KeyboardHooked)).unwrap();
+        tx.send(Event::Single("Kenny")).unwrap();
+        tx.send(Event::System(KeyboardUnhooked)).unwrap();
+        drop(tx);
+        consumer.join().unwrap();
+
+        // Then
+        assert_eq!(
+            block_on_stream(stream).collect::<Vec<_>>(),
+            vec![
+                Event::System(KeyboardHooked),
+                Event::Single("Kenny"),
+                Event::System(KeyboardUnhooked)
+            ]
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use crate::engine::Engine;
+use crate::fragment::Mappings;
+use crate::types::Event;
+use crate::types::HookAction;
+use crate::types::KeyPress;
+use crate::types::SystemAction;
+use core::hash::Hash;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A step of a scenario, see [`assert_scenario`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    /// Presses a key written as in a sequence, e.g. `s` or `<A-a>`.
+    Press(String),
+    /// Lets time pass on the scenario's clock, firing actions whose timeout is over.
+    Wait(Duration),
+    /// The next event not expected yet.
+    Expect(Event<A, T>),
+    /// No events besides the ones expected already.
+    ExpectNoEvents,
+    /// What happened to the last key pressed.
+    ExpectKey(HookAction),
+}
+
+impl<A, T> Display for Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Press(key) => write!(f, "press({})", key),
+            Step::Wait(duration) => write!(f, "wait({:?})", duration),
+            Step::Expect(event) => write!(f, "expect({:?})", event),
+            Step::ExpectNoEvents => write!(f, "expect_no_events()"),
+            Step::ExpectKey(hook_action) => write!(f, "expect_key({:?})", hook_action),
+        }
+    }
+}
+
+pub fn press<A, T>(key: &str) -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::Press(key.to_string())
+}
+
+pub fn wait<A, T>(duration: Duration) -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::Wait(duration)
+}
+
+pub fn expect_single<A, T>(action: A) -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::Expect(Event::Single(action))
+}
+
+pub fn expect_multi<A, T>(tag: T, actions: impl IntoIterator<Item = A>) -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::Expect(Event::Multi(tag, actions.into_iter().collect()))
+}
+
+pub fn expect_system<A, T>(action: SystemAction) -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::Expect(Event::System(action))
+}
+
+pub fn expect_no_events<A, T>() -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::ExpectNoEvents
+}
+
+pub fn expect_suppressed<A, T>() -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::ExpectKey(HookAction::Suppress)
+}
+
+pub fn expect_passed_on<A, T>() -> Step<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    Step::ExpectKey(HookAction::PassOn)
+}
+
+/// Why a scenario failed, along with what happened up to the failing step.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Failure {
+    /// The failing step, counting from 1. One past the last step for events nobody expected.
+    pub step: usize,
+    pub expected: String,
+    pub actual: String,
+    pub transcript: Vec<String>,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "scenario failed at step {}", self.step)?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        writeln!(f, "  actual:   {}", self.actual)?;
+
+        for (i, line) in self.transcript.iter().enumerate() {
+            let marker = if i + 1 == self.step { ">" } else { " " };
+            writeln!(f, "{} {:>3}. {}", marker, i + 1, line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the steps against the engine on a virtual clock, starting when the scenario does.
+pub fn check<A, T>(
+    mut engine: Engine<A, T>,
+    steps: impl IntoIterator<Item = Step<A, T>>,
+) -> Result<(), Failure>
+where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    let mut now = Instant::now();
+    let mut events = VecDeque::new();
+    let mut last_key = None;
+    let mut transcript = vec![];
+
+    let fail = |transcript: &Vec<String>, expected: String, actual: String| Failure {
+        step: transcript.len(),
+        expected,
+        actual,
+        transcript: transcript.clone(),
+    };
+
+    for step in steps {
+        transcript.push(step.to_string());
+
+        match step {
+            Step::Press(key) => {
+                let key_press: KeyPress = key
+                    .parse()
+                    .map_err(|message| fail(&transcript, "a key press".to_string(), message))?;
+                let (hook_action, fed) = engine.feed(&key_press, now);
+
+                last_key = Some(hook_action);
+                events.extend(fed);
+            }
+            Step::Wait(duration) => {
+                now += duration;
+                events.extend(engine.tick(now));
+            }
+            Step::Expect(expected) => match events.pop_front() {
+                Some(actual) if actual == expected => {}
+                actual => {
+                    return Err(fail(
+                        &transcript,
+                        format!("{:?}", expected),
+                        format!("{:?}", actual),
+                    ))
+                }
+            },
+            Step::ExpectNoEvents => {
+                if !events.is_empty() {
+                    return Err(fail(
+                        &transcript,
+                        "no events".to_string(),
+                        format!("{:?}", events),
+                    ));
+                }
+            }
+            Step::ExpectKey(expected) => {
+                if last_key != Some(expected) {
+                    return Err(fail(
+                        &transcript,
+                        format!("{:?}", expected),
+                        format!("{:?}", last_key),
+                    ));
+                }
+            }
+        }
+    }
+
+    if !events.is_empty() {
+        transcript.push("end of the scenario".to_string());
+        return Err(fail(
+            &transcript,
+            "no more events".to_string(),
+            format!("{:?}", events),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs a scenario against the mappings and panics with a transcript if it fails, or if some
+/// mappings conflict. Events are expected in the order they are sent, and every event has to be
+/// expected.
+///
+/// ```ignore
+/// assert_scenario(
+///     keymap! { "<A-a> [1-5]*" as ToggleChannels => ToggleChannel(n) on timeout },
+///     [
+///         press("<A-a>"),
+///         press("1"),
+///         press("3"),
+///         wait(DEFAULT_TIMEOUT),
+///         expect_multi(ToggleChannels, [ToggleChannel(1), ToggleChannel(3)]),
+///     ],
+/// );
+/// ```
+#[track_caller]
+pub fn assert_scenario<A, T>(
+    mappings: impl Into<Mappings<A, T>>,
+    steps: impl IntoIterator<Item = Step<A, T>>,
+) where
+    A: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+    T: 'static + PartialEq + Eq + Clone + Debug + Display + Sync + Send + Hash,
+{
+    let (engine, conflicts) = Engine::new(mappings);
+
+    if let Some(conflict) = conflicts.first() {
+        panic!("the mappings conflict: {}", conflict);
+    }
+
+    if let Err(failure) = check(engine, steps) {
+        panic!("{}", failure);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::DEFAULT_TIMEOUT;
+    use crate::keymap;
+
+    fn engine() -> Engine<&'static str, &'static str> {
+        let (engine, _) = Engine::new(keymap! {
+            "<A-a> w" => "Kenny",
+            "<A-a> [13]*" as "Channels" => { 1 => "One" on timeout, 3 => "Three" on timeout },
+        });
+
+        engine
+    }
+
+    #[test]
+    fn should_pass_a_scenario_that_matches() {
+        assert_eq!(
+            check(
+                engine(),
+                [
+                    press("<A-a>"),
+                    press("1"),
+                    press("3"),
+                    expect_suppressed(),
+                    expect_no_events(),
+                    wait(DEFAULT_TIMEOUT),
+                    expect_multi("Channels", ["One", "Three"]),
+                    press("x"),
+                    expect_passed_on(),
+                ],
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_point_at_the_failing_step() {
+        // When
+        let failure = check(
+            engine(),
+            [
+                press("<A-a>"),
+                press("1"),
+                wait(DEFAULT_TIMEOUT),
+                expect_multi("Channels", ["One", "Three"]),
+            ],
+        )
+        .unwrap_err();
+
+        // Then
+        assert_eq!(failure.step, 4);
+        assert_eq!(
+            failure.to_string(),
+            r#"scenario failed at step 4
+  expected: Multi("Channels", ["One", "Three"])
+  actual:   Some(Multi("Channels", ["One"]))
+    1. press(<A-a>)
+    2. press(1)
+    3. wait(650ms)
+>   4. expect(Multi("Channels", ["One", "Three"]))
+"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no more events")]
+    fn should_fail_on_events_nobody_expected() {
+        assert_scenario::<_, &str>(
+            keymap! { "<A-a> w" => "Kenny" },
+            [press("<A-a>"), press("w")],
+        );
+    }
+}
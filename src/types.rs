@@ -3,13 +3,15 @@ use core::fmt;
 use core::hash::Hash;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
 
-#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
 pub enum Modifier {
     NoMod, // TODO: Remove this.
     ModAlt,
     ModShift,
-    ModAltShift
+    ModAltShift,
 }
 
 impl Display for Modifier {
@@ -35,7 +37,29 @@ where
     Multi(T, Vec<A>),
 }
 
-#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+impl<A, T> Display for Event<A, T>
+where
+    A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::System(action) => write!(f, "{}", action),
+            Event::Single(action) => write!(f, "{}", action),
+            Event::Multi(tag, actions) => {
+                write!(f, "{}:", tag)?;
+
+                for (i, action) in actions.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, action)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
 #[allow(dead_code)]
 pub enum Key {
     Key0,
@@ -86,10 +110,10 @@ impl Display for Key {
             Key3 => write!(f, "3"),
             Key4 => write!(f, "4"),
             Key5 => write!(f, "5"),
-            Key6 => write!(f, "5"),
-            Key7 => write!(f, "5"),
-            Key8 => write!(f, "5"),
-            Key9 => write!(f, "5"),
+            Key6 => write!(f, "6"),
+            Key7 => write!(f, "7"),
+            Key8 => write!(f, "8"),
+            Key9 => write!(f, "9"),
             KeyA => write!(f, "A"),
             KeyB => write!(f, "B"),
             KeyC => write!(f, "C"),
@@ -130,6 +154,10 @@ impl Key {
             b'3' => Key3,
             b'4' => Key4,
             b'5' => Key5,
+            b'6' => Key6,
+            b'7' => Key7,
+            b'8' => Key8,
+            b'9' => Key9,
             b'A' => KeyA,
             b'B' => KeyB,
             b'C' => KeyC,
@@ -163,13 +191,41 @@ impl Key {
 
 use Key::*;
 
-#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
 pub enum KeyPress {
     // TODO: I think it makes more sense to remove 'NoMod' modifier from Modifier.
     // NoMod(Key),
     Mod(Key, Modifier),
 }
 
+impl From<keyboard_hook_notation::KeyPress> for KeyPress {
+    fn from(key: keyboard_hook_notation::KeyPress) -> Self {
+        let modifier = match key.modifier {
+            keyboard_hook_notation::Modifier::NoMod => NoMod,
+            keyboard_hook_notation::Modifier::ModAlt => ModAlt,
+            keyboard_hook_notation::Modifier::ModShift => ModShift,
+            keyboard_hook_notation::Modifier::ModAltShift => ModAltShift,
+        };
+
+        KeyPress::Mod(Key::from_u8(key.key.to_ascii_uppercase() as u8), modifier)
+    }
+}
+
+/// Parses a single key press written as in a sequence, e.g. `s` or `<A-a>`.
+impl FromStr for KeyPress {
+    type Err = String;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        keyboard_hook_notation::parse_key_press(token).map(KeyPress::from)
+    }
+}
+
+impl From<Key> for KeyPress {
+    fn from(key: Key) -> Self {
+        KeyPress::Mod(key, NoMod)
+    }
+}
+
 impl Display for KeyPress {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -239,6 +295,16 @@ where
             Behaviour::Shutdown(KeyPress::Mod(_, modifier)) => modifier,
         }
     }
+
+    /// Describes the behaviour the way it's written in `keymap!`.
+    pub fn describe(&self) -> String {
+        match self {
+            Behaviour::Timeout(_) => "timeout".to_string(),
+            Behaviour::Action(_, action) => action.to_string(),
+            Behaviour::ActionOnTimeout(_, action) => format!("{} on timeout", action),
+            Behaviour::Shutdown(_) => "shutdown".to_string(),
+        }
+    }
 }
 
 impl<A> Display for Behaviour<A>
@@ -329,6 +395,8 @@ use Mapping::*;
 pub enum SystemAction {
     KeyboardHooked,
     KeyboardUnhooked,
+    /// Handling a key press took longer than the budget. The OS removes hooks that are too slow.
+    OverBudget(Duration),
 }
 
 impl Display for SystemAction {
@@ -336,8 +404,94 @@ impl Display for SystemAction {
         match self {
             SystemAction::KeyboardHooked => write!(f, "KeyboardHooked"),
             SystemAction::KeyboardUnhooked => write!(f, "ShuttingDown"),
+            SystemAction::OverBudget(elapsed) => write!(f, "OverBudget({:?})", elapsed),
         }
     }
 }
 
 pub struct TerminateHook;
+
+/// Whether a key press is kept from other hooks and applications.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum HookAction {
+    Suppress,
+    PassOn,
+}
+
+/// What to do with actions waiting for a timeout when the hook is stopped.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum FlushPolicy {
+    /// Send them right away, as if the timeout had run out.
+    #[default]
+    Flush,
+    /// Drop them.
+    Discard,
+}
+
+pub(crate) fn format_keys(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(|key| key.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Where a fragment got mounted.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct MountPoint {
+    pub fragment: String,
+    pub path: Vec<KeyPress>,
+}
+
+/// A sequence that can't be mapped the way it was defined.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Conflict {
+    pub path: Vec<KeyPress>,
+    pub reason: String,
+    pub mount: Option<MountPoint>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", format_keys(&self.path), self.reason)?;
+
+        if let Some(mount) = &self.mount {
+            write!(
+                f,
+                " (in fragment '{}' mounted at {})",
+                mount.fragment,
+                format_keys(&mount.path)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_every_digit() {
+        // When
+        let keys: Vec<Key> = (b'0'..=b'9').map(Key::from_u8).collect();
+
+        // Then
+        assert_eq!(
+            keys,
+            [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9]
+        );
+        assert_eq!(
+            keys.iter().map(|key| key.to_string()).collect::<String>(),
+            "0123456789"
+        );
+    }
+
+    #[test]
+    fn should_parse_key_presses() {
+        assert_eq!("s".parse(), Ok(KeyPress::Mod(KeyS, NoMod)));
+        assert_eq!("<S-A-2>".parse(), Ok(KeyPress::Mod(Key2, ModAltShift)));
+        assert!("<C-a>".parse::<KeyPress>().is_err());
+        assert!("ab".parse::<KeyPress>().is_err());
+    }
+}
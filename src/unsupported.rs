@@ -0,0 +1,41 @@
+use crate::error::KeyboardHookError;
+use crate::key_handler::KeypressCallback;
+use crate::types::Event;
+use crate::types::FlushPolicy;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::sync::mpsc;
+
+/// Never exists, as no hook is ever installed.
+#[derive(Clone, Copy)]
+pub(crate) enum HookThread {}
+
+impl HookThread {
+    pub fn stop(&self, _policy: FlushPolicy) {
+        match *self {}
+    }
+}
+
+/// Stands in for the Windows backend on other platforms, failing with `UnsupportedPlatform`.
+pub(crate) struct KeyboardHookManager;
+
+impl KeyboardHookManager {
+    pub fn new() -> Result<Self, KeyboardHookError> {
+        Err(KeyboardHookError::UnsupportedPlatform)
+    }
+
+    pub fn hook<A, T>(
+        &mut self,
+        _sender: mpsc::Sender<Event<A, T>>,
+        _keypress_callback: Box<dyn KeypressCallback>,
+        _ready: impl FnOnce(HookThread),
+    ) -> Result<(), KeyboardHookError>
+    where
+        A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+        T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
+    {
+        Err(KeyboardHookError::UnsupportedPlatform)
+    }
+
+    pub fn stop_windows_loop() {}
+}
@@ -1,32 +1,54 @@
+use crate::error::KeyboardHookError;
+use crate::key_handler::KeypressCallback;
 use crate::types::Event;
+use crate::types::FlushPolicy;
+use crate::types::HookAction;
 use crate::types::Modifier;
 use crate::types::SystemAction::*;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::ptr;
+use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc;
-use winapi::shared::minwindef::{LPARAM, LRESULT, WPARAM};
+use winapi::shared::minwindef::{DWORD, LPARAM, LRESULT, WPARAM};
 use winapi::shared::windef::HHOOK;
 use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
 use winapi::um::winuser::{
-    CallNextHookEx, DispatchMessageW, GetKeyState, GetMessageW, PostQuitMessage, SetWindowsHookExW,
-    TranslateMessage, UnhookWindowsHookEx, KBDLLHOOKSTRUCT, MSG, WH_KEYBOARD_LL, WM_KEYDOWN,
-    WM_SYSKEYDOWN,
+    CallNextHookEx, DispatchMessageW, GetKeyState, GetMessageW, PeekMessageW, PostQuitMessage,
+    PostThreadMessageW, SetWindowsHookExW, TranslateMessage, UnhookWindowsHookEx, KBDLLHOOKSTRUCT,
+    MSG, PM_NOREMOVE, WH_KEYBOARD_LL, WM_APP, WM_KEYDOWN, WM_SYSKEYDOWN, WM_USER,
 };
 
 use winapi::um::winuser::{VK_LMENU, VK_LSHIFT};
 
-static mut HOOK_MANAGER: *mut KeyboardHookManager = ptr::null_mut();
+/// The manager whose hook is installed. Only one hook can be installed per process.
+static HOOK_MANAGER: AtomicPtr<KeyboardHookManager> = AtomicPtr::new(ptr::null_mut());
 
 const KEY_PRESSED_MASK: u16 = 0x8000;
 
-pub(crate) enum HookAction {
-    Suppress,
-    PassOn,
-}
+/// Posted to the hook thread to stop the hook, with the flush policy in `wParam`.
+const WM_STOP_HOOK: u32 = WM_APP + 1;
+
+/// The thread running the message loop of an installed hook.
+#[derive(Clone, Copy)]
+pub(crate) struct HookThread(DWORD);
+
+impl HookThread {
+    /// Asks the message loop to stop. Does nothing if it has stopped already.
+    pub fn stop(&self, policy: FlushPolicy) {
+        let policy = match policy {
+            FlushPolicy::Flush => 0,
+            FlushPolicy::Discard => 1,
+        };
 
-pub(crate) trait KeypressCallback {
-    fn handle(&mut self, key: u32, modifiers: &[Modifier]) -> HookAction;
+        unsafe {
+            PostThreadMessageW(self.0, WM_STOP_HOOK, policy, 0);
+        }
+    }
 }
 
 type BoxedKeypressCallback = Box<dyn KeypressCallback>;
@@ -37,7 +59,7 @@ pub(crate) struct KeyboardHookManager {
 }
 
 impl KeyboardHookManager {
-    pub fn new() -> Result<Self, &'static str> {
+    pub fn new() -> Result<Self, KeyboardHookError> {
         Ok(Self {
             hook: None,
             callback: None,
@@ -48,17 +70,24 @@ impl KeyboardHookManager {
         &mut self,
         sender: mpsc::Sender<Event<A, T>>,
         keypress_callback: BoxedKeypressCallback,
-    ) -> Result<(), &'static str>
+        ready: impl FnOnce(HookThread),
+    ) -> Result<(), KeyboardHookError>
     where
         A: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
         T: PartialEq + Eq + Clone + Debug + Display + Sync + Send,
     {
-        unsafe {
-            if !HOOK_MANAGER.is_null() {
-                return Err("Keyboard hook is already installed.");
-            }
+        let installed = HOOK_MANAGER.compare_exchange(
+            ptr::null_mut(),
+            self,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+
+        if installed.is_err() {
+            return Err(KeyboardHookError::AlreadyInstalled);
+        }
 
-            HOOK_MANAGER = self;
+        unsafe {
             self.callback = Some(keypress_callback);
 
             let hook = SetWindowsHookExW(
@@ -69,12 +98,26 @@ impl KeyboardHookManager {
             );
 
             if hook.is_null() {
-                return Err("Failed to install keyboard hook.");
+                let error = std::io::Error::last_os_error();
+                HOOK_MANAGER.store(ptr::null_mut(), Ordering::Release);
+                return Err(KeyboardHookError::InstallFailed(error));
             }
 
             self.hook = Some(hook);
-            sender.send(Event::System(KeyboardHooked)).unwrap();
-            Self::start_windows_loop();
+
+            // Make sure the thread has a message queue before anyone posts to it.
+            let mut msg: MSG = std::mem::zeroed();
+            PeekMessageW(
+                &mut msg,
+                std::ptr::null_mut(),
+                WM_USER,
+                WM_USER,
+                PM_NOREMOVE,
+            );
+            ready(HookThread(GetCurrentThreadId()));
+
+            sender.send(Event::System(KeyboardHooked))?;
+            self.start_windows_loop();
             Ok(())
         }
     }
@@ -85,10 +128,23 @@ impl KeyboardHookManager {
         }
     }
 
-    fn start_windows_loop() {
+    fn start_windows_loop(&mut self) {
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
             while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) != 0 {
+                if msg.hwnd.is_null() && msg.message == WM_STOP_HOOK {
+                    let policy = match msg.wParam {
+                        0 => FlushPolicy::Flush,
+                        _ => FlushPolicy::Discard,
+                    };
+
+                    if let Some(callback) = self.callback.as_mut() {
+                        callback.stop(policy);
+                    }
+
+                    break;
+                }
+
                 TranslateMessage(&msg);
                 DispatchMessageW(&msg);
             }
@@ -96,7 +152,7 @@ impl KeyboardHookManager {
     }
 
     fn get_instance() -> *mut KeyboardHookManager {
-        unsafe { HOOK_MANAGER }
+        HOOK_MANAGER.load(Ordering::Acquire)
     }
 
     unsafe extern "system" fn low_level_keyboard_proc(
@@ -110,9 +166,10 @@ impl KeyboardHookManager {
         }
 
         let manager = &mut *manager_ptr;
-        let hook = manager.hook.expect("No hook found!");
-        let callback: &mut Box<dyn KeypressCallback> =
-            manager.callback.as_mut().expect("No callback found!");
+        let hook = manager.hook.unwrap_or(ptr::null_mut());
+        let Some(callback) = manager.callback.as_mut() else {
+            return CallNextHookEx(hook, n_code, w_param, l_param);
+        };
 
         if n_code != 0 || (w_param != WM_KEYDOWN as WPARAM && w_param != WM_SYSKEYDOWN as WPARAM) {
             return CallNextHookEx(hook, n_code, w_param, l_param);
@@ -120,17 +177,22 @@ impl KeyboardHookManager {
 
         let p_keyboard: &KBDLLHOOKSTRUCT = &*(l_param as *const KBDLLHOOKSTRUCT);
 
-        let mut modifiers = vec![];
-
-        if (GetKeyState(VK_LMENU) as u16 & KEY_PRESSED_MASK) != 0 {
-            modifiers.push(Modifier::ModAlt);
-        }
-
-        if (GetKeyState(VK_LSHIFT) as u16 & KEY_PRESSED_MASK) != 0 {
-            modifiers.push(Modifier::ModShift);
+        let alt = (GetKeyState(VK_LMENU) as u16 & KEY_PRESSED_MASK) != 0;
+        let shift = (GetKeyState(VK_LSHIFT) as u16 & KEY_PRESSED_MASK) != 0;
+        let modifiers: &[Modifier] = match (alt, shift) {
+            (true, true) => &[Modifier::ModAlt, Modifier::ModShift],
+            (true, false) => &[Modifier::ModAlt],
+            (false, true) => &[Modifier::ModShift],
+            (false, false) => &[],
         };
 
-        match callback.handle(p_keyboard.vkCode, &modifiers) {
+        // Unwinding out of the hook procedure would abort the process, pass the key on instead.
+        let action = panic::catch_unwind(AssertUnwindSafe(|| {
+            callback.handle(p_keyboard.vkCode, modifiers)
+        }))
+        .unwrap_or(HookAction::PassOn);
+
+        match action {
             HookAction::Suppress => 1,
             HookAction::PassOn => CallNextHookEx(hook, n_code, w_param, l_param),
         }
@@ -142,8 +204,14 @@ impl Drop for KeyboardHookManager {
         if let Some(hook) = self.hook {
             unsafe {
                 UnhookWindowsHookEx(hook);
-                HOOK_MANAGER = ptr::null_mut();
             }
+
+            let _ = HOOK_MANAGER.compare_exchange(
+                self,
+                ptr::null_mut(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
         }
     }
 }